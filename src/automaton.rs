@@ -0,0 +1,359 @@
+use crate::cycle::{self, Outcome};
+use std::hash::{Hash, Hasher};
+
+// A synchronous 2D cellular automaton. Each step computes every cell's next
+// value from its neighbourhood in the current grid, using a second buffer so
+// that the whole step sees the same generation.
+
+#[derive(Clone, Copy, Debug, PartialEq, Hash)]
+pub enum Boundary<T> {
+    // Cells outside the grid always read as this value
+    Fixed(T),
+    // The grid sits in an infinite background of this value. The background
+    // is updated by the same rule as everything else, and the grid grows by
+    // one cell on every side each step to take in the cells it affects.
+    Infinite(T),
+    // The grid wraps around at its edges
+    Toroidal,
+}
+
+#[derive(Debug)]
+pub struct Automaton<T> {
+    rows: usize,
+    cols: usize,
+    cells: Vec<T>,
+    scratch: Vec<T>,
+    boundary: Boundary<T>,
+}
+
+// What a rule gets to look at: the cells around one position of the grid
+pub struct Neighbourhood<'a, T> {
+    automaton: &'a Automaton<T>,
+    r: isize,
+    c: isize,
+    // The index of the cell when its whole 3x3 block is inside the grid, so
+    // reads within it need no boundary handling
+    interior: Option<usize>,
+}
+
+impl<'a, T: Copy + PartialEq> Neighbourhood<'a, T> {
+    #[inline]
+    pub fn get(&self, dr: isize, dc: isize) -> T {
+        match self.interior {
+            Some(i) if dr.abs() <= 1 && dc.abs() <= 1 => {
+                self.automaton.cells[(i as isize + dr * self.automaton.cols as isize + dc) as usize]
+            }
+            _ => self.automaton.get(self.r + dr, self.c + dc),
+        }
+    }
+
+    pub fn center(&self) -> T {
+        self.get(0, 0)
+    }
+
+    // The 3x3 block around the cell, row by row
+    pub fn moore(&self) -> [T; 9] {
+        let mut cells = [self.center(); 9];
+        for (i, cell) in cells.iter_mut().enumerate() {
+            *cell = self.get(i as isize / 3 - 1, i as isize % 3 - 1);
+        }
+        cells
+    }
+
+    // Up, left, center, right, down
+    pub fn von_neumann(&self) -> [T; 5] {
+        [
+            self.get(-1, 0),
+            self.get(0, -1),
+            self.center(),
+            self.get(0, 1),
+            self.get(1, 0),
+        ]
+    }
+}
+
+impl<T: Copy + PartialEq> Automaton<T> {
+    pub fn new(grid: &[Vec<T>], boundary: Boundary<T>) -> Result<Automaton<T>, String> {
+        let cols = grid.first().map_or(0, |row| row.len());
+        if grid.iter().any(|row| row.len() != cols) {
+            return Err("Grid rows have different lengths".into());
+        }
+        if boundary == Boundary::Toroidal && (grid.is_empty() || cols == 0) {
+            return Err("A toroidal grid can't be empty".into());
+        }
+
+        Ok(Automaton {
+            rows: grid.len(),
+            cols,
+            cells: grid.iter().flatten().copied().collect(),
+            scratch: Vec::new(),
+            boundary,
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    // The value of every cell outside the grid, if there is one
+    pub fn background(&self) -> Option<T> {
+        match self.boundary {
+            Boundary::Fixed(v) | Boundary::Infinite(v) => Some(v),
+            Boundary::Toroidal => None,
+        }
+    }
+
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    pub fn to_rows(&self) -> Vec<Vec<T>> {
+        self.cells
+            .chunks(self.cols.max(1))
+            .map(|row| row.to_vec())
+            .collect()
+    }
+
+    // Any position may be read; those outside the grid go by the boundary
+    #[inline]
+    pub fn get(&self, r: isize, c: isize) -> T {
+        // Negative positions wrap round to huge ones, so one comparison each
+        // tells whether a position is inside the grid
+        if (r as usize) < self.rows && (c as usize) < self.cols {
+            return self.cells[r as usize * self.cols + c as usize];
+        }
+        self.get_outside(r, c)
+    }
+
+    fn get_outside(&self, r: isize, c: isize) -> T {
+        match self.boundary {
+            Boundary::Fixed(v) | Boundary::Infinite(v) => v,
+            Boundary::Toroidal => {
+                let (rows, cols) = (self.rows as isize, self.cols as isize);
+                self.cells[(r.rem_euclid(rows) * cols + c.rem_euclid(cols)) as usize]
+            }
+        }
+    }
+
+    // Applies `rule` to every cell at once and returns how many cells changed
+    // (for an infinite grid, counting the cells it grew by that differ from
+    // the old background)
+    pub fn step<F>(&mut self, rule: F) -> usize
+    where
+        F: Fn(&Neighbourhood<T>) -> T,
+    {
+        let grow = match self.boundary {
+            Boundary::Infinite(_) => 1,
+            _ => 0,
+        };
+        let rows = self.rows + 2 * grow;
+        let cols = self.cols + 2 * grow;
+
+        let mut next = std::mem::take(&mut self.scratch);
+        next.clear();
+        // Every cell is overwritten below, this only sizes the buffer
+        if let Some(fill) = self.background().or_else(|| self.cells.first().copied()) {
+            next.resize(rows * cols, fill);
+        }
+
+        let mut changed = 0;
+        for (r, row) in next.chunks_mut(cols.max(1)).enumerate() {
+            let r = r as isize - grow as isize;
+            let interior_row = r >= 1 && r + 1 < self.rows as isize;
+            for (c, cell) in row.iter_mut().enumerate() {
+                let c = c as isize - grow as isize;
+                let interior = interior_row && c >= 1 && c + 1 < self.cols as isize;
+                let n = Neighbourhood {
+                    automaton: self,
+                    r,
+                    c,
+                    interior: interior.then(|| (r * self.cols as isize + c) as usize),
+                };
+                let v = rule(&n);
+                if v != n.center() {
+                    changed += 1;
+                }
+                *cell = v;
+            }
+        }
+
+        if let Boundary::Infinite(background) = self.boundary {
+            // Two cells out from the grid, the whole neighbourhood is background
+            let n = Neighbourhood {
+                automaton: self,
+                r: -2,
+                c: -2,
+                interior: None,
+            };
+            let v = rule(&n);
+            if v != background {
+                self.boundary = Boundary::Infinite(v);
+            }
+        }
+
+        self.scratch = std::mem::replace(&mut self.cells, next);
+        self.rows = rows;
+        self.cols = cols;
+
+        changed
+    }
+}
+
+// The scratch buffer is only working space, so copies leave it out and it
+// plays no part in comparing or hashing automatons
+impl<T: Clone> Clone for Automaton<T> {
+    fn clone(&self) -> Self {
+        Automaton {
+            rows: self.rows,
+            cols: self.cols,
+            cells: self.cells.clone(),
+            scratch: Vec::new(),
+            boundary: self.boundary.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.rows = source.rows;
+        self.cols = source.cols;
+        self.cells.clone_from(&source.cells);
+        self.boundary = source.boundary.clone();
+    }
+}
+
+impl<T: PartialEq> PartialEq for Automaton<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.rows == other.rows
+            && self.cols == other.cols
+            && self.cells == other.cells
+            && self.boundary == other.boundary
+    }
+}
+
+impl<T: Hash> Hash for Automaton<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rows.hash(state);
+        self.cols.hash(state);
+        self.cells.hash(state);
+        self.boundary.hash(state);
+    }
+}
+
+impl<T: Copy + PartialEq + Hash> Automaton<T> {
+    // Calls `step_fn` (which makes one full step, of however many rule
+    // applications) until the state repeats or `max_steps` steps have been
+    // made. See `cycle::run`, which may call `step_fn` on copies too.
+    pub fn run<F>(&mut self, max_steps: Option<usize>, step_fn: F) -> Outcome
+    where
+        F: FnMut(&mut Automaton<T>),
+    {
+        cycle::run(self, max_steps, step_fn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn life(n: &Neighbourhood<u8>) -> u8 {
+        let alive = n.moore().iter().sum::<u8>() - n.center();
+        match (n.center(), alive) {
+            (1, 2) | (_, 3) => 1,
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let grid = vec![vec![1, 2], vec![3, 4]];
+
+        let fixed = Automaton::new(&grid, Boundary::Fixed(9)).unwrap();
+        assert_eq!(9, fixed.get(-1, 0));
+        assert_eq!(4, fixed.get(1, 1));
+
+        let torus = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+        assert_eq!(3, torus.get(-1, 0));
+        assert_eq!(2, torus.get(2, 3));
+
+        assert!(Automaton::new(&[vec![1], vec![1, 2]], Boundary::Fixed(0)).is_err());
+        assert!(Automaton::<u8>::new(&[], Boundary::Toroidal).is_err());
+    }
+
+    #[test]
+    fn test_blinker() {
+        let grid = vec![vec![0, 1, 0], vec![0, 1, 0], vec![0, 1, 0]];
+        let mut automaton = Automaton::new(&grid, Boundary::Fixed(0)).unwrap();
+
+        assert_eq!(4, automaton.step(life));
+        assert_eq!(
+            vec![vec![0, 0, 0], vec![1, 1, 1], vec![0, 0, 0]],
+            automaton.to_rows()
+        );
+
+        let outcome = automaton.run(Some(10), |a| {
+            a.step(life);
+        });
+        assert_eq!(
+            Outcome::Cycle {
+                start: 0,
+                period: 2
+            },
+            outcome
+        );
+    }
+
+    #[test]
+    fn test_fixed_point_and_limit() {
+        let block = vec![vec![1, 1], vec![1, 1]];
+        let mut automaton = Automaton::new(&block, Boundary::Fixed(0)).unwrap();
+        let outcome = automaton.run(None, |a| {
+            a.step(life);
+        });
+        assert_eq!(Outcome::FixedPoint { step: 1 }, outcome);
+
+        // A glider on a torus never settles within a handful of steps
+        let glider = vec![
+            vec![0, 1, 0, 0, 0, 0],
+            vec![0, 0, 1, 0, 0, 0],
+            vec![1, 1, 1, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+        ];
+        let mut automaton = Automaton::new(&glider, Boundary::Toroidal).unwrap();
+        let outcome = automaton.run(Some(5), |a| {
+            a.step(life);
+        });
+        assert_eq!(Outcome::StepLimit, outcome);
+
+        // ...but comes back around after 4 steps per cell of the torus
+        let outcome = automaton.run(None, |a| {
+            a.step(life);
+        });
+        assert_eq!(
+            Outcome::Cycle {
+                start: 0,
+                period: 24
+            },
+            outcome
+        );
+    }
+
+    #[test]
+    fn test_infinite_background() {
+        // Every cell flips, including the background
+        let mut automaton = Automaton::new(&[vec![1u8]], Boundary::Infinite(0)).unwrap();
+
+        assert_eq!(9, automaton.step(|n| 1 - n.center()));
+        assert_eq!((3, 3), (automaton.rows(), automaton.cols()));
+        assert_eq!(Some(1), automaton.background());
+        assert_eq!(
+            vec![vec![1, 1, 1], vec![1, 0, 1], vec![1, 1, 1]],
+            automaton.to_rows()
+        );
+        assert_eq!(1, automaton.get(100, -100));
+    }
+}
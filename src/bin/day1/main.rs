@@ -1,39 +1,187 @@
+use std::collections::VecDeque;
 use std::error::Error;
+use std::io::BufRead;
 
-fn count_3window_increases(values: &[i32]) -> i32 {
-    let three_window_sums: Vec<i32> = values
-        .iter()
-        .zip(values.iter().skip(1))
-        .zip(values.iter().skip(2))
-        .map(|((i, j), k)| i + j + k)
-        .collect();
+mod report;
 
-    count_increases(&three_window_sums[..])
-}
+// Neighbouring windows of k readings share all but their first and last
+// readings, so the sum goes up exactly when values[i + k] > values[i]. A
+// window size of 1 compares each reading with the one before it. Only the
+// last k readings are kept, so `values` can be a feed of any length.
+fn count_window_increases<I>(values: I, k: usize) -> usize
+where
+    I: IntoIterator,
+    I::Item: PartialOrd,
+{
+    // Empty windows all sum to nothing, so none of them is an increase
+    if k == 0 {
+        return 0;
+    }
 
-fn count_increases(values: &[i32]) -> i32 {
+    let mut window = VecDeque::with_capacity(k);
     let mut increases = 0;
-    for (i, v) in values.iter().zip(values.iter().skip(1)) {
-        if v > i {
+
+    for value in values {
+        if window.len() == k && window.pop_front().is_some_and(|first| value > first) {
             increases += 1;
         }
+        window.push_back(value);
     }
 
     increases
 }
 
+// Same as `count_window_increases`, for readings one per line. A window size
+// of 0 is rejected rather than left to skip over the whole feed unread.
+fn count_window_increases_streaming<R: BufRead>(
+    reader: R,
+    k: usize,
+) -> Result<usize, Box<dyn Error>> {
+    if k == 0 {
+        return Err("Window size must be at least 1".into());
+    }
+
+    // The first bad line stops the feed, and is reported once it has
+    let mut error: Option<Box<dyn Error>> = None;
+    let values = reader.lines().enumerate().map_while(|(i, line)| {
+        let value = match line {
+            Ok(line) => line
+                .trim()
+                .parse::<i32>()
+                .map_err(|e| format!("Line {}: {}: {:?}", i + 1, e, line).into()),
+            Err(e) => Err(e.into()),
+        };
+        value.map_err(|e| error = Some(e)).ok()
+    });
+
+    let increases = count_window_increases(values, k);
+    match error {
+        Some(e) => Err(e),
+        None => Ok(increases),
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
+    let window = aoc2021::parse_flag_value::<usize>("window")?;
+    if window == Some(0) {
+        return Err("--window must be at least 1".into());
+    }
+
+    if aoc2021::has_flag("stream") {
+        let k = window.unwrap_or(3);
+        let increases = count_window_increases_streaming(aoc2021::get_input_reader()?, k)?;
+        println!("Window of {}: {}", k, increases);
+        return Ok(());
+    }
+
     let input = aoc2021::get_input_string()?;
     let values = input
         .lines()
         .map(|l| l.parse())
         .collect::<Result<Vec<i32>, _>>()?;
 
-    let part1 = count_increases(&values);
+    if aoc2021::has_flag("report") {
+        let z_window = aoc2021::parse_flag_value::<usize>("z-window")?.unwrap_or(20);
+        let z_score = aoc2021::parse_flag_value::<f64>("z-score")?.unwrap_or(3.0);
+        if z_window < 2 {
+            return Err("--z-window must be at least 2".into());
+        }
+        if !(z_score > 0.0 && z_score.is_finite()) {
+            return Err("--z-score must be a positive number".into());
+        }
+
+        let report = report::report(&values, z_window, z_score);
+        match aoc2021::get_flag_value("format").as_deref() {
+            None | Some("text") => print!("{}", report),
+            Some("json") => println!("{}", report.to_json()),
+            Some(other) => {
+                return Err(format!("Unknown --format {:?}, expected text or json", other).into())
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(k) = window {
+        let increases = count_window_increases(values.iter().copied(), k);
+        println!("Window of {}: {}", k, increases);
+        return Ok(());
+    }
+
+    let part1 = count_window_increases(values.iter().copied(), 1);
     println!("Part 1: {}", part1);
 
-    let part2 = count_3window_increases(&values);
+    let part2 = count_window_increases(values.iter().copied(), 3);
     println!("Part 2: {}", part2);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: [i32; 10] = [199, 200, 208, 210, 200, 207, 240, 269, 260, 263];
+
+    // Sums every window and compares neighbouring sums, the long way round
+    fn count_by_sums(values: &[i32], k: usize) -> usize {
+        let sums = values
+            .windows(k)
+            .map(|w| w.iter().sum())
+            .collect::<Vec<i32>>();
+        sums.windows(2).filter(|w| w[1] > w[0]).count()
+    }
+
+    #[test]
+    fn test_sample() {
+        assert_eq!(7, count_window_increases(SAMPLE, 1));
+        assert_eq!(5, count_window_increases(SAMPLE.iter().copied(), 3));
+        assert_eq!(0, count_window_increases(SAMPLE, 10));
+        assert_eq!(0, count_window_increases(SAMPLE, 11));
+        assert_eq!(0, count_window_increases(Vec::<i32>::new(), 3));
+        assert_eq!(0, count_window_increases(SAMPLE, 0));
+
+        // Any readings that can be compared will do, from an iterator that
+        // can't be cloned
+        let mut readings = SAMPLE.map(i64::from).to_vec();
+        assert_eq!(5, count_window_increases(readings.drain(..), 3));
+    }
+
+    #[test]
+    fn test_agrees_with_sums() {
+        let values = (0..200)
+            .map(|i: i32| (i * 7919) % 263 - (i * 31) % 97)
+            .collect::<Vec<_>>();
+        for k in 1..=12 {
+            assert_eq!(
+                count_by_sums(&values, k),
+                count_window_increases(values.clone(), k)
+            );
+        }
+    }
+
+    #[test]
+    fn test_streaming() {
+        let input = SAMPLE.map(|v| v.to_string()).join("\n");
+        for k in 1..=11 {
+            assert_eq!(
+                count_window_increases(SAMPLE, k),
+                count_window_increases_streaming(input.as_bytes(), k).unwrap()
+            );
+        }
+        assert!(count_window_increases_streaming(input.as_bytes(), 0).is_err());
+
+        // A long feed, read from a buffer of bytes like a file would be
+        let readings = (0..1_000_000).map(|i: i32| (i * 37) % 1000);
+        let feed = readings
+            .clone()
+            .map(|v| format!("{}\n", v))
+            .collect::<String>();
+        assert_eq!(
+            count_window_increases(readings, 3),
+            count_window_increases_streaming(std::io::Cursor::new(feed), 3).unwrap()
+        );
+
+        let err = count_window_increases_streaming("1\n2\nthree\n".as_bytes(), 1).unwrap_err();
+        assert!(err.to_string().starts_with("Line 3:"), "{}", err);
+    }
+}
@@ -0,0 +1,330 @@
+use std::fmt;
+
+// Positions are 0-based indices into the readings; they're shown as 1-based
+// line numbers
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    count: usize,
+    min: i32,
+    max: i32,
+    mean: f64,
+}
+
+// `len` strictly increasing readings starting at `start`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Run {
+    start: usize,
+    len: usize,
+}
+
+// The change from reading `index` to the one after it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Jump {
+    index: usize,
+    from: i32,
+    to: i32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outlier {
+    index: usize,
+    value: i32,
+    z: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    summary: Option<Summary>,
+    longest_run: Option<Run>,
+    largest_jump: Option<Jump>,
+    // Readings more than `threshold` standard deviations from the mean of the
+    // `window` readings before them
+    window: usize,
+    threshold: f64,
+    outliers: Vec<Outlier>,
+}
+
+fn summarize(values: &[i32]) -> Option<Summary> {
+    Some(Summary {
+        count: values.len(),
+        min: *values.iter().min()?,
+        max: *values.iter().max()?,
+        mean: values.iter().map(|v| *v as f64).sum::<f64>() / values.len() as f64,
+    })
+}
+
+// Ties go to the earliest run
+fn longest_increasing_run(values: &[i32]) -> Option<Run> {
+    let mut best: Option<Run> = None;
+    let mut start = 0;
+
+    for i in 0..values.len() {
+        if i > 0 && values[i] <= values[i - 1] {
+            start = i;
+        }
+        let len = i - start + 1;
+        if best.as_ref().is_none_or(|b| len > b.len) {
+            best = Some(Run { start, len });
+        }
+    }
+
+    best
+}
+
+// The biggest change either way between neighbouring readings, the earliest
+// if there's a tie
+fn largest_jump(values: &[i32]) -> Option<Jump> {
+    let change = |i: usize| (values[i + 1] as i64 - values[i] as i64).abs();
+    let index = (0..values.len().saturating_sub(1)).reduce(|best, i| {
+        if change(i) > change(best) {
+            i
+        } else {
+            best
+        }
+    })?;
+
+    Some(Jump {
+        index,
+        from: values[index],
+        to: values[index + 1],
+    })
+}
+
+// The mean and variance of the window are kept up to date with running sums,
+// so this is O(n) whatever the window size. Windows whose readings are all
+// the same have no spread to measure against and are skipped.
+fn find_outliers(values: &[i32], window: usize, threshold: f64) -> Vec<Outlier> {
+    let mut outliers = Vec::new();
+    let (mut sum, mut sum_sq) = (0f64, 0f64);
+
+    for (i, &v) in values.iter().enumerate() {
+        if i >= window {
+            let n = window as f64;
+            let mean = sum / n;
+            let variance = (sum_sq / n - mean * mean).max(0.0);
+            if variance > 0.0 {
+                let z = (v as f64 - mean) / variance.sqrt();
+                if z.abs() > threshold {
+                    outliers.push(Outlier {
+                        index: i,
+                        value: v,
+                        z,
+                    });
+                }
+            }
+
+            let old = values[i - window] as f64;
+            sum -= old;
+            sum_sq -= old * old;
+        }
+
+        sum += v as f64;
+        sum_sq += (v as f64) * (v as f64);
+    }
+
+    outliers
+}
+
+pub fn report(values: &[i32], window: usize, threshold: f64) -> Report {
+    Report {
+        summary: summarize(values),
+        longest_run: longest_increasing_run(values),
+        largest_jump: largest_jump(values),
+        window,
+        threshold,
+        outliers: find_outliers(values, window, threshold),
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.summary {
+            Some(s) => writeln!(
+                f,
+                "Readings: {} (min {}, max {}, mean {:.2})",
+                s.count, s.min, s.max, s.mean
+            )?,
+            None => return writeln!(f, "Readings: 0"),
+        }
+
+        if let Some(run) = &self.longest_run {
+            writeln!(
+                f,
+                "Longest increasing run: {} readings, lines {}-{}",
+                run.len,
+                run.start + 1,
+                run.start + run.len
+            )?;
+        }
+
+        match &self.largest_jump {
+            Some(jump) => writeln!(
+                f,
+                "Largest jump: {:+} from line {} to line {} ({} -> {})",
+                jump.to as i64 - jump.from as i64,
+                jump.index + 1,
+                jump.index + 2,
+                jump.from,
+                jump.to
+            )?,
+            None => writeln!(f, "Largest jump: none")?,
+        }
+
+        writeln!(
+            f,
+            "Outliers (|z| > {} against the previous {} readings): {}",
+            self.threshold,
+            self.window,
+            self.outliers.len()
+        )?;
+        for o in self.outliers.iter() {
+            writeln!(f, "  line {}: {} (z = {:.2})", o.index + 1, o.value, o.z)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Report {
+    pub fn to_json(&self) -> String {
+        let summary = match &self.summary {
+            Some(s) => format!(
+                r#"{{"count":{},"min":{},"max":{},"mean":{}}}"#,
+                s.count, s.min, s.max, s.mean
+            ),
+            None => r#"{"count":0,"min":null,"max":null,"mean":null}"#.to_string(),
+        };
+        let run = match &self.longest_run {
+            Some(run) => format!(
+                r#"{{"length":{},"start_line":{},"end_line":{}}}"#,
+                run.len,
+                run.start + 1,
+                run.start + run.len
+            ),
+            None => "null".to_string(),
+        };
+        let jump = match &self.largest_jump {
+            Some(jump) => format!(
+                r#"{{"change":{},"line":{},"from":{},"to":{}}}"#,
+                jump.to as i64 - jump.from as i64,
+                jump.index + 1,
+                jump.from,
+                jump.to
+            ),
+            None => "null".to_string(),
+        };
+        let outliers = self
+            .outliers
+            .iter()
+            .map(|o| {
+                format!(
+                    r#"{{"line":{},"value":{},"z":{}}}"#,
+                    o.index + 1,
+                    o.value,
+                    o.z
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!(
+            r#"{{"summary":{},"longest_increasing_run":{},"largest_jump":{},"outliers":{{"window":{},"threshold":{},"readings":[{}]}}}}"#,
+            summary, run, jump, self.window, self.threshold, outliers
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: [i32; 10] = [199, 200, 208, 210, 200, 207, 240, 269, 260, 263];
+
+    #[test]
+    fn test_sample_report() {
+        let r = report(&SAMPLE, 3, 2.0);
+
+        assert_eq!(
+            Some(Summary {
+                count: 10,
+                min: 199,
+                max: 269,
+                mean: 2256.0 / 10.0
+            }),
+            r.summary
+        );
+        // Two runs of 4; the first one wins
+        assert_eq!(Some(Run { start: 0, len: 4 }), r.longest_run);
+        assert_eq!(
+            Some(Jump {
+                index: 5,
+                from: 207,
+                to: 240
+            }),
+            r.largest_jump
+        );
+
+        // 240 after 210, 200 and 207 is way out
+        assert_eq!(
+            vec![6, 7],
+            r.outliers.iter().map(|o| o.index).collect::<Vec<_>>()
+        );
+        assert!((r.outliers[0].z - 8.19).abs() < 0.01, "{}", r.outliers[0].z);
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        let r = report(&[], 5, 3.0);
+        assert_eq!(
+            (None, None, None),
+            (r.summary, r.longest_run, r.largest_jump)
+        );
+        assert_eq!("Readings: 0\n", report(&[], 5, 3.0).to_string());
+
+        let r = report(&[5], 5, 3.0);
+        assert_eq!(Some(Run { start: 0, len: 1 }), r.longest_run);
+        assert_eq!(None, r.largest_jump);
+
+        // A flat window has no spread, so a jump after it isn't scored
+        let r = report(&[1, 1, 1, 1, 100, 1], 4, 3.0);
+        assert!(r.outliers.is_empty());
+
+        // Ties go to the first run and the first jump; drops count as jumps
+        let r = report(&[3, 1, 2, 0, 1], 2, 3.0);
+        assert_eq!(Some(Run { start: 1, len: 2 }), r.longest_run);
+        assert_eq!(
+            Some(Jump {
+                index: 0,
+                from: 3,
+                to: 1
+            }),
+            r.largest_jump
+        );
+    }
+
+    #[test]
+    fn test_output() {
+        let r = report(&SAMPLE, 3, 2.0);
+
+        let text = r.to_string();
+        assert!(text.starts_with("Readings: 10 (min 199, max 269, mean 225.60)\n"));
+        assert!(text.contains("Longest increasing run: 4 readings, lines 1-4\n"));
+        assert!(text.contains("Largest jump: +33 from line 6 to line 7 (207 -> 240)\n"));
+        assert!(text.contains("  line 7: 240 (z = 8.19)\n"));
+
+        let json = r.to_json();
+        assert!(json.starts_with(r#"{"summary":{"count":10,"min":199,"max":269,"mean":225.6},"#));
+        assert!(
+            json.contains(r#""longest_increasing_run":{"length":4,"start_line":1,"end_line":4}"#)
+        );
+        assert!(json.contains(r#""largest_jump":{"change":33,"line":6,"from":207,"to":240}"#));
+        assert!(json.contains(r#""outliers":{"window":3,"threshold":2,"readings":[{"line":7,"#));
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+
+        assert_eq!(
+            r#"{"summary":{"count":0,"min":null,"max":null,"mean":null},"longest_increasing_run":null,"largest_jump":null,"outliers":{"window":3,"threshold":2,"readings":[]}}"#,
+            report(&[], 3, 2.0).to_json()
+        );
+    }
+}
@@ -3,7 +3,7 @@ use std::error::Error;
 fn main() -> Result<(), Box<dyn Error>> {
     let _input = aoc2021::get_input_string()?
         .lines()
-        .filter(|l| l.trim().len() > 0)
+        .filter(|l| !l.trim().is_empty())
         .collect::<Vec<_>>();
     Ok(())
 }
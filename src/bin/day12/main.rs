@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::error::Error;
 
+#[allow(dead_code)]
 #[derive(Debug)]
 struct Node {
     name: String,
@@ -51,7 +52,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 mod tests {
     use crate::parse_input;
 
-    const SAMPLE_INPUT: &'static str = "start-A
+    const SAMPLE_INPUT: &str = "start-A
 start-b
 A-c
 A-b
@@ -62,7 +63,7 @@ b-end";
     #[test]
     fn test_deserialize() {
         let graph = parse_input(SAMPLE_INPUT);
-        let mut node_names = graph.nodes.iter().map(|(k, _v)| k).collect::<Vec<_>>();
+        let mut node_names = graph.nodes.keys().collect::<Vec<_>>();
         node_names.sort();
 
         assert_eq!(node_names, vec!["A", "b", "c", "d", "end", "start"]);
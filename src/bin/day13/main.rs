@@ -9,6 +9,7 @@ enum Axis {
     Y,
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 struct Fold {
     axis: Axis,
@@ -20,7 +21,7 @@ fn parse_input(input: &str) -> (Dots, Vec<Fold>) {
 
     let dots = lines
         .by_ref()
-        .take_while(|l| l.trim().len() > 0)
+        .take_while(|l| !l.trim().is_empty())
         .map(|l| {
             let mut components = l.split(',');
             (
@@ -69,7 +69,7 @@ where
             Err(String::from("Not enough bits to read int"))?;
         }
     }
-    Ok(val.into())
+    Ok(val)
 }
 
 fn parse_packet<I>(bits: &mut I) -> Result<Packet, Box<dyn Error>>
@@ -168,7 +168,7 @@ fn version_sum(packet: &Packet) -> u64 {
         | Expr::GreaterThan(pkts)
         | Expr::LessThan(pkts)
         | Expr::EqualTo(pkts) => {
-            let subsum: u64 = pkts.iter().map(|p| version_sum(p)).sum();
+            let subsum: u64 = pkts.iter().map(version_sum).sum();
             (packet.version as u64) + subsum
         }
     }
@@ -176,14 +176,11 @@ fn version_sum(packet: &Packet) -> u64 {
 
 fn eval_packet(pkt: &Packet) -> u64 {
     match &pkt.value {
-        Expr::Literal(v) => *v as u64,
-        Expr::Sum(pkts) => pkts.iter().map(|p| eval_packet(p)).sum(),
-        Expr::Product(pkts) => pkts
-            .iter()
-            .map(|p| eval_packet(p))
-            .fold(1u64, |acc, v| acc * v),
-        Expr::Minimum(pkts) => pkts.iter().map(|p| eval_packet(p)).min().unwrap(),
-        Expr::Maximum(pkts) => pkts.iter().map(|p| eval_packet(p)).max().unwrap(),
+        Expr::Literal(v) => *v,
+        Expr::Sum(pkts) => pkts.iter().map(eval_packet).sum(),
+        Expr::Product(pkts) => pkts.iter().map(eval_packet).product(),
+        Expr::Minimum(pkts) => pkts.iter().map(eval_packet).min().unwrap(),
+        Expr::Maximum(pkts) => pkts.iter().map(eval_packet).max().unwrap(),
         Expr::GreaterThan(pkts) => (eval_packet(&pkts[0]) > eval_packet(&pkts[1])).into(),
 
         Expr::LessThan(pkts) => (eval_packet(&pkts[0]) < eval_packet(&pkts[1])).into(),
@@ -0,0 +1,328 @@
+use super::{SnailfishNumber, SnailfishRules};
+use std::thread;
+
+// A snailfish number stored as its regular values in left-to-right order,
+// each tagged with the number of pairs that enclose it. Adding two numbers
+// is a concatenation and reducing edits the vector in place, so nothing gets
+// rebuilt (or cloned) node by node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlatNumber {
+    elems: Vec<(u64, u32)>,
+}
+
+impl From<&SnailfishNumber> for FlatNumber {
+    fn from(n: &SnailfishNumber) -> Self {
+        fn flatten(n: &SnailfishNumber, depth: u32, elems: &mut Vec<(u64, u32)>) {
+            match n {
+                SnailfishNumber::Regular(v) => elems.push((*v, depth)),
+                SnailfishNumber::Pair(l, r) => {
+                    flatten(l, depth + 1, elems);
+                    flatten(r, depth + 1, elems);
+                }
+            }
+        }
+
+        let mut elems = Vec::new();
+        flatten(n, 0, &mut elems);
+        FlatNumber { elems }
+    }
+}
+
+impl From<&FlatNumber> for SnailfishNumber {
+    fn from(n: &FlatNumber) -> Self {
+        fn build<I>(it: &mut std::iter::Peekable<I>, depth: u32) -> SnailfishNumber
+        where
+            I: Iterator<Item = (u64, u32)>,
+        {
+            match it.peek() {
+                Some(&(v, d)) if d == depth => {
+                    it.next();
+                    SnailfishNumber::Regular(v)
+                }
+                Some(_) => {
+                    let l = build(it, depth + 1);
+                    let r = build(it, depth + 1);
+                    SnailfishNumber::Pair(l.into(), r.into())
+                }
+                None => panic!("Malformed flat snailfish number"),
+            }
+        }
+
+        build(&mut n.elems.iter().copied().peekable(), 0)
+    }
+}
+
+fn do_explode(elems: &mut Vec<(u64, u32)>, rules: &SnailfishRules) -> bool {
+    let i = match elems.iter().position(|&(_, d)| d > rules.explode_depth) {
+        Some(i) => i,
+        None => return false,
+    };
+
+    // Pairs deep enough to explode only ever hold regular numbers, so the
+    // exploding pair is the two elements starting at i
+    let (lv, d) = elems[i];
+    let (rv, _) = elems[i + 1];
+    if i > 0 {
+        elems[i - 1].0 += lv;
+    }
+    if i + 2 < elems.len() {
+        elems[i + 2].0 += rv;
+    }
+    elems[i] = (0, d - 1);
+    elems.remove(i + 1);
+
+    true
+}
+
+fn do_split(elems: &mut Vec<(u64, u32)>, rules: &SnailfishRules) -> bool {
+    let i = match elems.iter().position(|&(v, _)| v >= rules.split_threshold) {
+        Some(i) => i,
+        None => return false,
+    };
+
+    let (v, d) = elems[i];
+    elems[i] = (v / 2, d + 1);
+    elems.insert(i + 1, (v / 2 + (v % 2), d + 1));
+
+    true
+}
+
+pub fn reduce(mut n: FlatNumber, rules: &SnailfishRules) -> FlatNumber {
+    while do_explode(&mut n.elems, rules) || do_split(&mut n.elems, rules) {}
+    n
+}
+
+pub fn add(a: &FlatNumber, b: &FlatNumber, rules: &SnailfishRules) -> FlatNumber {
+    let mut elems = Vec::with_capacity(a.elems.len() + b.elems.len());
+    elems.extend(
+        a.elems
+            .iter()
+            .chain(b.elems.iter())
+            .map(|&(v, d)| (v, d + 1)),
+    );
+    reduce(FlatNumber { elems }, rules)
+}
+
+pub fn magnitude(n: &FlatNumber, rules: &SnailfishRules) -> u64 {
+    // Whenever the top two entries of the stack sit at the same depth they're
+    // the two halves of one pair, so fold them into that pair's magnitude
+    let mut stack: Vec<(u64, u32)> = Vec::with_capacity(n.elems.len());
+    for &elem in n.elems.iter() {
+        stack.push(elem);
+        while stack.len() >= 2 && stack[stack.len() - 1].1 == stack[stack.len() - 2].1 {
+            let (rv, d) = stack.pop().unwrap();
+            let (lv, _) = stack.pop().unwrap();
+            stack.push((rules.left_weight * lv + rules.right_weight * rv, d - 1));
+        }
+    }
+
+    stack.pop().map_or(0, |(v, _)| v)
+}
+
+// Best sum among the ordered pairs whose left operand is one of `rows`. Ties
+// go to the pair that comes first, same as the sequential search.
+fn best_in_rows<I>(
+    nums: &[FlatNumber],
+    rows: I,
+    rules: &SnailfishRules,
+) -> Option<(u64, (usize, usize))>
+where
+    I: Iterator<Item = usize>,
+{
+    let mut best: Option<(u64, (usize, usize))> = None;
+
+    for i in rows {
+        for (j, b) in nums.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+
+            let cur_magnitude = magnitude(&add(&nums[i], b, rules), rules);
+            if best.is_none_or(|(max, _)| cur_magnitude > max) {
+                best = Some((cur_magnitude, (i, j)));
+            }
+        }
+    }
+
+    best
+}
+
+pub fn part2(nums: &[FlatNumber], rules: &SnailfishRules) -> Option<(u64, (usize, usize))> {
+    best_in_rows(nums, 0..nums.len(), rules)
+}
+
+// Same as `part2`, with the left operands dealt out round-robin to `workers`
+// threads (at least one)
+pub fn part2_parallel(
+    nums: &[FlatNumber],
+    workers: usize,
+    rules: &SnailfishRules,
+) -> Option<(u64, (usize, usize))> {
+    let workers = workers.clamp(1, nums.len().max(1));
+
+    thread::scope(|s| {
+        let handles = (0..workers)
+            .map(|w| s.spawn(move || best_in_rows(nums, (w..nums.len()).step_by(workers), rules)))
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .filter_map(|h| h.join().expect("part 2 worker panicked"))
+            .max_by(|(a, a_idx), (b, b_idx)| a.cmp(b).then(b_idx.cmp(a_idx)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as tree;
+    use aoc2021::testing::Rng;
+    use std::time::Instant;
+
+    // Regular numbers sit at most one level below the pairs that explode,
+    // which is the deepest `reduce` accepts
+    fn random_number(rng: &mut Rng, max_value: u64, rules: &SnailfishRules) -> SnailfishNumber {
+        fn gen(rng: &mut Rng, depth: u32, max_value: u64, max_depth: u32) -> SnailfishNumber {
+            if depth == max_depth || (depth > 0 && rng.next_u64().is_multiple_of(3)) {
+                SnailfishNumber::Regular(rng.next_u64() % (max_value + 1))
+            } else {
+                SnailfishNumber::Pair(
+                    gen(rng, depth + 1, max_value, max_depth).into(),
+                    gen(rng, depth + 1, max_value, max_depth).into(),
+                )
+            }
+        }
+
+        gen(rng, 0, max_value, rules.explode_depth + 1)
+    }
+
+    fn random_homework(rng: &mut Rng, len: usize, rules: &SnailfishRules) -> Vec<SnailfishNumber> {
+        (0..len)
+            .map(|_| tree::reduce(random_number(rng, 9, rules), rules))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = Rng(0x5eed);
+        for _ in 0..500 {
+            let n = random_number(&mut rng, 20, &SnailfishRules::default());
+            assert_eq!(n, SnailfishNumber::from(&FlatNumber::from(&n)));
+            assert_eq!(n, n.to_string().parse::<SnailfishNumber>().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_reduce_agrees_with_tree() {
+        let rules = SnailfishRules::default();
+        let mut rng = Rng(0xdecafbad);
+        for _ in 0..500 {
+            let n = random_number(&mut rng, 20, &rules);
+            let flat = reduce(FlatNumber::from(&n), &rules);
+            assert_eq!(tree::reduce(n, &rules), SnailfishNumber::from(&flat));
+        }
+    }
+
+    #[test]
+    fn test_add_and_magnitude_agree_with_tree() {
+        let rules = SnailfishRules::default();
+        let mut rng = Rng(0xfeedface);
+        let nums = random_homework(&mut rng, 200, &rules);
+        for pair in nums.chunks(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            let tree_sum = tree::add(a.clone(), b.clone(), &rules);
+            let flat_sum = add(&a.into(), &b.into(), &rules);
+
+            assert_eq!(tree_sum, SnailfishNumber::from(&flat_sum));
+            assert_eq!(
+                tree::magnitude(&tree_sum, &rules),
+                magnitude(&flat_sum, &rules)
+            );
+        }
+    }
+
+    #[test]
+    fn test_variant_rules_agree_with_tree() {
+        let mut rng = Rng(0x0dd5);
+        for explode_depth in [1, 2, 5] {
+            let rules = SnailfishRules {
+                explode_depth,
+                split_threshold: 2 + rng.next_u64() % 15,
+                left_weight: rng.next_u64() % 10,
+                right_weight: rng.next_u64() % 10,
+            };
+
+            for _ in 0..30 {
+                let n = random_number(&mut rng, 20, &rules);
+                let flat = reduce(FlatNumber::from(&n), &rules);
+                assert_eq!(tree::reduce(n, &rules), SnailfishNumber::from(&flat));
+            }
+
+            let nums = random_homework(&mut rng, 5, &rules);
+            let flat_nums = nums.iter().map(FlatNumber::from).collect::<Vec<_>>();
+            assert_eq!(
+                tree::part2(&nums, &rules),
+                part2_parallel(&flat_nums, 3, &rules)
+            );
+        }
+    }
+
+    #[test]
+    fn test_part2() {
+        let rules = SnailfishRules::default();
+        let mut rng = Rng(0xabad1dea);
+        let nums = random_homework(&mut rng, 15, &rules);
+        let flat_nums = nums.iter().map(FlatNumber::from).collect::<Vec<_>>();
+
+        assert_eq!(tree::part2(&nums, &rules), part2(&flat_nums, &rules));
+        for workers in 1..=6 {
+            assert_eq!(
+                part2(&flat_nums, &rules),
+                part2_parallel(&flat_nums, workers, &rules)
+            );
+        }
+
+        assert_eq!(None, part2_parallel(&flat_nums[..1], 4, &rules));
+        assert_eq!(None, part2_parallel(&[], 4, &rules));
+    }
+
+    #[test]
+    fn test_part2_sample() {
+        let rules = SnailfishRules::default();
+        let nums = include_str!("./sample.txt")
+            .lines()
+            .map(|l| FlatNumber::from(&l.parse::<SnailfishNumber>().unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(Some((3993, (8, 0))), part2(&nums, &rules));
+        assert_eq!(Some((3993, (8, 0))), part2_parallel(&nums, 3, &rules));
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_part2() {
+        let rules = SnailfishRules::default();
+        let mut rng = Rng(0xbe4c4);
+        let nums = random_homework(&mut rng, 100, &rules);
+        let flat_nums = nums.iter().map(FlatNumber::from).collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let tree_max = tree::part2(&nums, &rules);
+        let tree_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        let flat_max = part2(&flat_nums, &rules);
+        let flat_elapsed = start.elapsed();
+
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        let start = Instant::now();
+        let parallel_max = part2_parallel(&flat_nums, workers, &rules);
+        let parallel_elapsed = start.elapsed();
+
+        assert_eq!(tree_max, flat_max);
+        assert_eq!(flat_max, parallel_max);
+        println!("boxed tree: {:?}", tree_elapsed);
+        println!("flat:       {:?}", flat_elapsed);
+        println!("flat, {} workers: {:?}", workers, parallel_elapsed);
+    }
+}
@@ -2,6 +2,11 @@ use std::error::Error;
 use std::fmt;
 use std::iter::Peekable;
 use std::str::FromStr;
+use std::thread;
+
+mod flat;
+
+use flat::FlatNumber;
 
 #[derive(Clone, Debug, PartialEq)]
 enum SnailfishNumber {
@@ -9,53 +14,144 @@ enum SnailfishNumber {
     Pair(Box<SnailfishNumber>, Box<SnailfishNumber>),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    OpenBracket,
+    CloseBracket,
+    Comma,
+    Digit,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::OpenBracket => write!(f, "'['"),
+            Token::CloseBracket => write!(f, "']'"),
+            Token::Comma => write!(f, "','"),
+            Token::Digit => write!(f, "a digit"),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+const NUMBER_START: &[Token] = &[Token::OpenBracket, Token::Digit];
+
+// Offsets count characters (not bytes) from the start of the string,
+// including any whitespace the parser skipped over
+#[derive(Debug, PartialEq)]
 enum SnailfishParseError {
-    PrematureEnd,
-    InvalidChar,
+    PrematureEnd {
+        offset: usize,
+        expected: &'static [Token],
+    },
+    InvalidChar {
+        offset: usize,
+        found: char,
+        expected: &'static [Token],
+    },
 }
 
 impl Error for SnailfishParseError {}
 
 impl fmt::Display for SnailfishParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_expected(f: &mut fmt::Formatter, expected: &[Token]) -> fmt::Result {
+            for (i, token) in expected.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " or ")?;
+                }
+                write!(f, "{}", token)?;
+            }
+            Ok(())
+        }
+
         match self {
-            &SnailfishParseError::InvalidChar => write!(f, "Encountered invalid character"),
-            &SnailfishParseError::PrematureEnd => write!(f, "Unexpected end of input"),
+            SnailfishParseError::InvalidChar {
+                offset,
+                found,
+                expected,
+            } => {
+                write!(
+                    f,
+                    "Invalid character {:?} at offset {}, expected ",
+                    found, offset
+                )?;
+                write_expected(f, expected)
+            }
+            SnailfishParseError::PrematureEnd { offset, expected } => {
+                write!(f, "Unexpected end of input at offset {}, expected ", offset)?;
+                write_expected(f, expected)
+            }
         }
     }
 }
 
-fn read_sn<I>(it: &mut Peekable<I>) -> Result<SnailfishNumber, SnailfishParseError>
+fn expect_char<I>(
+    it: &mut Peekable<I>,
+    end: usize,
+    c: char,
+    expected: &'static [Token],
+) -> Result<(), SnailfishParseError>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    match it.next() {
+        Some((_, found)) if found == c => Ok(()),
+        Some((offset, found)) => Err(SnailfishParseError::InvalidChar {
+            offset,
+            found,
+            expected,
+        }),
+        None => Err(SnailfishParseError::PrematureEnd {
+            offset: end,
+            expected,
+        }),
+    }
+}
+
+fn read_sn<I>(it: &mut Peekable<I>, end: usize) -> Result<SnailfishNumber, SnailfishParseError>
 where
-    I: Iterator<Item = char>,
+    I: Iterator<Item = (usize, char)>,
 {
-    let c = it.next().ok_or(SnailfishParseError::PrematureEnd)?;
+    // A regular number may still have more digits, so those are acceptable
+    // wherever one could end
+    fn after(n: &SnailfishNumber, token: Token) -> &'static [Token] {
+        match (n, token) {
+            (SnailfishNumber::Regular(_), Token::Comma) => &[Token::Digit, Token::Comma],
+            (SnailfishNumber::Regular(_), Token::CloseBracket) => {
+                &[Token::Digit, Token::CloseBracket]
+            }
+            (_, Token::Comma) => &[Token::Comma],
+            _ => &[Token::CloseBracket],
+        }
+    }
+
+    let (offset, c) = it.next().ok_or(SnailfishParseError::PrematureEnd {
+        offset: end,
+        expected: NUMBER_START,
+    })?;
     match c {
         '[' => {
-            let left = read_sn(it)?;
-            if it.next().ok_or(SnailfishParseError::PrematureEnd)? != ',' {
-                return Err(SnailfishParseError::InvalidChar);
-            }
-            let right = read_sn(it)?;
-            if it.next().ok_or(SnailfishParseError::PrematureEnd)? != ']' {
-                return Err(SnailfishParseError::InvalidChar);
-            }
+            let left = read_sn(it, end)?;
+            expect_char(it, end, ',', after(&left, Token::Comma))?;
+            let right = read_sn(it, end)?;
+            expect_char(it, end, ']', after(&right, Token::CloseBracket))?;
 
             Ok(SnailfishNumber::Pair(left.into(), right.into()))
         }
         '0'..='9' => {
             let mut val = u64::from(c) - u64::from('0');
-            while it
-                .peek()
-                .ok_or(SnailfishParseError::PrematureEnd)?
-                .is_ascii_digit()
-            {
-                val = val * 10 + (u64::from(it.next().unwrap()) - u64::from('0'));
+            while let Some((_, d)) = it.next_if(|(_, d)| d.is_ascii_digit()) {
+                val = val * 10 + (u64::from(d) - u64::from('0'));
             }
             Ok(SnailfishNumber::Regular(val))
         }
-        _ => Err(SnailfishParseError::InvalidChar),
+        found => Err(SnailfishParseError::InvalidChar {
+            offset,
+            found,
+            expected: NUMBER_START,
+        }),
     }
 }
 
@@ -63,7 +159,127 @@ impl FromStr for SnailfishNumber {
     type Err = SnailfishParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        read_sn(&mut s.chars().filter(|c| !c.is_whitespace()).peekable())
+        let end = s.chars().count();
+        let mut it = s
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| !c.is_whitespace())
+            .peekable();
+
+        let n = read_sn(&mut it, end)?;
+        if let Some((offset, found)) = it.next() {
+            let expected = match n {
+                SnailfishNumber::Regular(_) => &[Token::Digit, Token::End][..],
+                _ => &[Token::End][..],
+            };
+            return Err(SnailfishParseError::InvalidChar {
+                offset,
+                found,
+                expected,
+            });
+        }
+
+        Ok(n)
+    }
+}
+
+// The canonical `[a,b]` form, which `FromStr` reads back
+impl fmt::Display for SnailfishNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnailfishNumber::Regular(v) => write!(f, "{}", v),
+            SnailfishNumber::Pair(l, r) => write!(f, "[{},{}]", l, r),
+        }
+    }
+}
+
+impl SnailfishNumber {
+    // Displays the number spread over several lines, one element per line
+    // prefixed by its depth, flagging the pairs that are about to explode
+    // under `rules`
+    fn pretty<'a>(&'a self, rules: &'a SnailfishRules) -> Pretty<'a> {
+        Pretty { n: self, rules }
+    }
+}
+
+struct Pretty<'a> {
+    n: &'a SnailfishNumber,
+    rules: &'a SnailfishRules,
+}
+
+impl fmt::Display for Pretty<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_pretty(
+            n: &SnailfishNumber,
+            depth: u32,
+            rules: &SnailfishRules,
+            f: &mut fmt::Formatter,
+        ) -> fmt::Result {
+            let indent = "  ".repeat(depth as usize);
+            match n {
+                SnailfishNumber::Pair(_, _) if depth >= rules.explode_depth => {
+                    writeln!(f, "{} {}{}  <- explodes", depth, indent, n)
+                }
+                SnailfishNumber::Pair(l, r) => {
+                    writeln!(f, "{} {}[", depth, indent)?;
+                    write_pretty(l, depth + 1, rules, f)?;
+                    write_pretty(r, depth + 1, rules, f)?;
+                    writeln!(f, "{} {}]", depth, indent)
+                }
+                SnailfishNumber::Regular(v) => writeln!(f, "{} {}{}", depth, indent, v),
+            }
+        }
+
+        write_pretty(self.n, 0, self.rules, f)
+    }
+}
+
+// Knobs for the reduction and magnitude rules. Pairs nested inside
+// `explode_depth` others explode, regular numbers of at least
+// `split_threshold` split, and a pair's magnitude is `left_weight` times its
+// left half plus `right_weight` times its right half.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SnailfishRules {
+    explode_depth: u32,
+    split_threshold: u64,
+    left_weight: u64,
+    right_weight: u64,
+}
+
+impl Default for SnailfishRules {
+    fn default() -> Self {
+        SnailfishRules {
+            explode_depth: 4,
+            split_threshold: 10,
+            left_weight: 3,
+            right_weight: 2,
+        }
+    }
+}
+
+impl SnailfishRules {
+    fn from_flags() -> Result<Self, Box<dyn Error>> {
+        let default = SnailfishRules::default();
+        let rules = SnailfishRules {
+            explode_depth: aoc2021::parse_flag_value("explode-depth")?
+                .unwrap_or(default.explode_depth),
+            split_threshold: aoc2021::parse_flag_value("split-threshold")?
+                .unwrap_or(default.split_threshold),
+            left_weight: aoc2021::parse_flag_value("left-weight")?.unwrap_or(default.left_weight),
+            right_weight: aoc2021::parse_flag_value("right-weight")?
+                .unwrap_or(default.right_weight),
+        };
+
+        // Shallower explosions would blow up the top-level pair, and anything
+        // below 2 splits into a pair that still holds a splittable number
+        if rules.explode_depth < 1 {
+            return Err("--explode-depth must be at least 1".into());
+        }
+        if rules.split_threshold < 2 {
+            return Err("--split-threshold must be at least 2".into());
+        }
+
+        Ok(rules)
     }
 }
 
@@ -87,7 +303,11 @@ fn add_to_rightmost(n: SnailfishNumber, v: u64) -> SnailfishNumber {
     }
 }
 
-fn do_explode(n: SnailfishNumber, depth: u32) -> (SnailfishNumber, Option<Edit>) {
+fn do_explode(
+    n: SnailfishNumber,
+    depth: u32,
+    rules: &SnailfishRules,
+) -> (SnailfishNumber, Option<Edit>) {
     fn get_regular_value(n: SnailfishNumber) -> u64 {
         match n {
             SnailfishNumber::Regular(v) => v,
@@ -95,23 +315,29 @@ fn do_explode(n: SnailfishNumber, depth: u32) -> (SnailfishNumber, Option<Edit>)
         }
     }
 
-    match (n, depth) {
-        (SnailfishNumber::Regular(v), 0..=4) => (SnailfishNumber::Regular(v), None),
-        (SnailfishNumber::Pair(l, r), 0..=2) => match do_explode(*l, depth + 1) {
-            (l, Some(Edit::AddRight(v))) => (
-                SnailfishNumber::Pair(l.into(), add_to_leftmost(*r, v).into()),
-                Some(Edit::Complete),
-            ),
-            (l, Some(edit)) => (SnailfishNumber::Pair(l.into(), r), Some(edit)),
-            (l, None) => match do_explode(*r, depth + 1) {
-                (r, Some(Edit::AddLeft(v))) => (
-                    SnailfishNumber::Pair(add_to_rightmost(l, v).into(), r.into()),
+    // `parent_depth` is where the pairs that explode hang off their parent
+    let parent_depth = rules.explode_depth - 1;
+    match n {
+        SnailfishNumber::Regular(v) if depth <= rules.explode_depth => {
+            (SnailfishNumber::Regular(v), None)
+        }
+        SnailfishNumber::Pair(l, r) if depth < parent_depth => {
+            match do_explode(*l, depth + 1, rules) {
+                (l, Some(Edit::AddRight(v))) => (
+                    SnailfishNumber::Pair(l.into(), add_to_leftmost(*r, v).into()),
                     Some(Edit::Complete),
                 ),
-                (r, opt) => (SnailfishNumber::Pair(l.into(), r.into()), opt),
-            },
-        },
-        (SnailfishNumber::Pair(l, r), 3) => match (*l, *r) {
+                (l, Some(edit)) => (SnailfishNumber::Pair(l.into(), r), Some(edit)),
+                (l, None) => match do_explode(*r, depth + 1, rules) {
+                    (r, Some(Edit::AddLeft(v))) => (
+                        SnailfishNumber::Pair(add_to_rightmost(l, v).into(), r.into()),
+                        Some(Edit::Complete),
+                    ),
+                    (r, opt) => (SnailfishNumber::Pair(l.into(), r.into()), opt),
+                },
+            }
+        }
+        SnailfishNumber::Pair(l, r) if depth == parent_depth => match (*l, *r) {
             (SnailfishNumber::Pair(ll, lr), r) => {
                 let llv = get_regular_value(*ll);
                 let lrv = get_regular_value(*lr);
@@ -143,17 +369,17 @@ fn do_explode(n: SnailfishNumber, depth: u32) -> (SnailfishNumber, Option<Edit>)
     }
 }
 
-fn do_split(n: SnailfishNumber) -> (SnailfishNumber, bool) {
+fn do_split(n: SnailfishNumber, rules: &SnailfishRules) -> (SnailfishNumber, bool) {
     match n {
-        SnailfishNumber::Pair(l, r) => match do_split(*l) {
+        SnailfishNumber::Pair(l, r) => match do_split(*l, rules) {
             (l, false) => {
-                let (r, have_split) = do_split(*r);
+                let (r, have_split) = do_split(*r, rules);
                 (SnailfishNumber::Pair(l.into(), r.into()), have_split)
             }
             (l, true) => (SnailfishNumber::Pair(l.into(), r), true),
         },
         SnailfishNumber::Regular(v) => {
-            if v >= 10 {
+            if v >= rules.split_threshold {
                 (
                     SnailfishNumber::Pair(
                         SnailfishNumber::Regular(v / 2).into(),
@@ -168,15 +394,15 @@ fn do_split(n: SnailfishNumber) -> (SnailfishNumber, bool) {
     }
 }
 
-fn reduce(mut n: SnailfishNumber) -> SnailfishNumber {
+fn reduce(mut n: SnailfishNumber, rules: &SnailfishRules) -> SnailfishNumber {
     loop {
-        let (reduced, edit) = do_explode(n, 0);
+        let (reduced, edit) = do_explode(n, 0, rules);
         if edit.is_some() {
             n = reduced;
             continue;
         }
 
-        let (reduced, have_split) = do_split(reduced);
+        let (reduced, have_split) = do_split(reduced, rules);
         if !have_split {
             return reduced;
         }
@@ -184,19 +410,129 @@ fn reduce(mut n: SnailfishNumber) -> SnailfishNumber {
     }
 }
 
-fn add(a: SnailfishNumber, b: SnailfishNumber) -> SnailfishNumber {
-    reduce(SnailfishNumber::Pair(a.into(), b.into()))
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Branch {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ReduceAction {
+    Explode(Vec<Branch>),
+    Split(Vec<Branch>),
+}
+
+impl fmt::Display for ReduceAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, path) = match self {
+            ReduceAction::Explode(path) => ("explode", path),
+            ReduceAction::Split(path) => ("split", path),
+        };
+        if path.is_empty() {
+            return write!(f, "{} at root", name);
+        }
+
+        let path = path
+            .iter()
+            .map(|b| if *b == Branch::Left { 'L' } else { 'R' })
+            .collect::<String>();
+        write!(f, "{} at {}", name, path)
+    }
+}
+
+// Path to the pair that `do_explode` would explode: the leftmost one nested
+// inside `rules.explode_depth` others
+fn find_explode_path(
+    n: &SnailfishNumber,
+    depth: u32,
+    rules: &SnailfishRules,
+) -> Option<Vec<Branch>> {
+    match n {
+        SnailfishNumber::Regular(_) => None,
+        SnailfishNumber::Pair(_, _) if depth == rules.explode_depth => Some(Vec::new()),
+        SnailfishNumber::Pair(l, r) => {
+            let (branch, mut path) = find_explode_path(l, depth + 1, rules)
+                .map(|p| (Branch::Left, p))
+                .or_else(|| find_explode_path(r, depth + 1, rules).map(|p| (Branch::Right, p)))?;
+            path.insert(0, branch);
+            Some(path)
+        }
+    }
+}
+
+// Path to the regular number that `do_split` would split
+fn find_split_path(n: &SnailfishNumber, rules: &SnailfishRules) -> Option<Vec<Branch>> {
+    match n {
+        SnailfishNumber::Regular(v) => (*v >= rules.split_threshold).then(Vec::new),
+        SnailfishNumber::Pair(l, r) => {
+            let (branch, mut path) = find_split_path(l, rules)
+                .map(|p| (Branch::Left, p))
+                .or_else(|| find_split_path(r, rules).map(|p| (Branch::Right, p)))?;
+            path.insert(0, branch);
+            Some(path)
+        }
+    }
+}
+
+// Walks through the same reduction as `reduce`, yielding the number after
+// every explode or split along with where it happened
+struct ReduceSteps {
+    cur: Option<SnailfishNumber>,
+    rules: SnailfishRules,
+}
+
+impl Iterator for ReduceSteps {
+    type Item = (ReduceAction, SnailfishNumber);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.cur.take()?;
+
+        let rules = &self.rules;
+        let (action, reduced) = if let Some(path) = find_explode_path(&n, 0, rules) {
+            (ReduceAction::Explode(path), do_explode(n, 0, rules).0)
+        } else if let Some(path) = find_split_path(&n, rules) {
+            (ReduceAction::Split(path), do_split(n, rules).0)
+        } else {
+            return None;
+        };
+
+        self.cur = Some(reduced.clone());
+        Some((action, reduced))
+    }
+}
+
+fn reduce_steps(n: SnailfishNumber, rules: &SnailfishRules) -> ReduceSteps {
+    ReduceSteps {
+        cur: Some(n),
+        rules: *rules,
+    }
+}
+
+fn add(a: SnailfishNumber, b: SnailfishNumber, rules: &SnailfishRules) -> SnailfishNumber {
+    reduce(SnailfishNumber::Pair(a.into(), b.into()), rules)
+}
+
+// Number of pairs enclosing the most deeply nested regular number
+fn max_depth(n: &SnailfishNumber) -> u32 {
+    match n {
+        SnailfishNumber::Regular(_) => 0,
+        SnailfishNumber::Pair(l, r) => 1 + max_depth(l).max(max_depth(r)),
+    }
 }
 
-fn magnitude(a: &SnailfishNumber) -> u64 {
+fn magnitude(a: &SnailfishNumber, rules: &SnailfishRules) -> u64 {
     match a {
         SnailfishNumber::Regular(v) => *v,
-        SnailfishNumber::Pair(l, r) => 3 * magnitude(&l) + 2 * magnitude(&r),
+        SnailfishNumber::Pair(l, r) => {
+            rules.left_weight * magnitude(l, rules) + rules.right_weight * magnitude(r, rules)
+        }
     }
 }
 
-fn part2(nums: &Vec<SnailfishNumber>) -> u64 {
-    let mut max_sum_magnitude: Option<u64> = None;
+// Returns the largest magnitude of a sum of two different numbers, along with
+// the indices of the numbers that were added to get it
+fn part2(nums: &[SnailfishNumber], rules: &SnailfishRules) -> Option<(u64, (usize, usize))> {
+    let mut best: Option<(u64, (usize, usize))> = None;
 
     for i in 0..nums.len() {
         for j in 0..nums.len() {
@@ -205,34 +541,96 @@ fn part2(nums: &Vec<SnailfishNumber>) -> u64 {
                 continue;
             }
 
-            let cur_magnitude = magnitude(&add(nums[i].clone(), nums[j].clone()));
+            let cur_magnitude = magnitude(&add(nums[i].clone(), nums[j].clone(), rules), rules);
 
-            max_sum_magnitude =
-                Some(max_sum_magnitude.map_or(cur_magnitude, |v| v.max(cur_magnitude)));
+            if best.is_none_or(|(max, _)| cur_magnitude > max) {
+                best = Some((cur_magnitude, (i, j)));
+            }
         }
     }
 
-    max_sum_magnitude.unwrap()
+    best
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let input = aoc2021::get_input_string()?;
+    let workers = match aoc2021::parse_flag_value::<usize>("workers")? {
+        Some(0) => return Err("--workers must be at least 1".into()),
+        Some(n) => n,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let rules = SnailfishRules::from_flags()?;
 
     let nums = input
         .lines()
-        .into_iter()
-        .map(|l| l.parse::<SnailfishNumber>())
+        .enumerate()
+        .map(|(i, l)| {
+            l.parse::<SnailfishNumber>()
+                .map_err(|e| format!("Line {}: {}", i + 1, e))
+        })
         .collect::<Result<Vec<_>, _>>()?;
 
-    let sum = nums
-        .clone()
+    // Reduction can only explode pairs holding regular numbers, so anything
+    // nested deeper than that can't be added. Inputs that are reduced under
+    // the puzzle's rules may not be under other ones, so reduce them first.
+    if let Some(i) = nums
+        .iter()
+        .position(|n| max_depth(n) > rules.explode_depth + 1)
+    {
+        return Err(format!(
+            "Line {}: nested too deeply to reduce with an explode depth of {}",
+            i + 1,
+            rules.explode_depth
+        )
+        .into());
+    }
+    let nums = nums
         .into_iter()
-        .reduce(|a, b| add(a, b))
-        .ok_or("Empty input list?")?;
-
-    println!("Part 1: {}", magnitude(&sum));
-
-    println!("Part 2: {}", part2(&nums));
+        .map(|n| reduce(n, &rules))
+        .collect::<Vec<_>>();
+
+    let sum = if aoc2021::has_flag("trace") {
+        nums.clone()
+            .into_iter()
+            .reduce(|a, b| {
+                let mut sum = SnailfishNumber::Pair(a.into(), b.into());
+                print!("after addition:\n{}", sum.pretty(&rules));
+                for (action, n) in reduce_steps(sum.clone(), &rules) {
+                    println!("after {}: {}", action, n);
+                    sum = n;
+                }
+                println!();
+                sum
+            })
+            .ok_or("Empty input list?")?
+    } else {
+        nums.clone()
+            .into_iter()
+            .reduce(|a, b| add(a, b, &rules))
+            .ok_or("Empty input list?")?
+    };
+
+    println!("Part 1: {}", magnitude(&sum, &rules));
+
+    // The boxed representation is kept around for comparison; it clones
+    // both operands for every pair and is much slower
+    let part2 = if aoc2021::has_flag("tree") {
+        part2(&nums, &rules)
+    } else {
+        let flat_nums = nums.iter().map(FlatNumber::from).collect::<Vec<_>>();
+        if workers == 1 {
+            flat::part2(&flat_nums, &rules)
+        } else {
+            flat::part2_parallel(&flat_nums, workers, &rules)
+        }
+    };
+    let (max_magnitude, (i, j)) = part2.ok_or("Need at least two numbers")?;
+    println!(
+        "Part 2: {} (line {} + line {})",
+        max_magnitude,
+        i + 1,
+        j + 1
+    );
 
     Ok(())
 }
@@ -258,19 +656,131 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Ok(SnailfishNumber::Regular(5)),
+            "5".parse::<SnailfishNumber>()
+        );
+        assert_eq!(
+            Ok(SnailfishNumber::Regular(123)),
+            " 123 ".parse::<SnailfishNumber>()
+        );
+
+        assert_eq!(
+            Err(SnailfishParseError::PrematureEnd {
+                offset: 0,
+                expected: NUMBER_START,
+            }),
+            "".parse::<SnailfishNumber>()
+        );
+        assert_eq!(
+            Err(SnailfishParseError::PrematureEnd {
+                offset: 5,
+                expected: &[Token::Digit, Token::CloseBracket],
+            }),
+            "[1, 2".parse::<SnailfishNumber>()
+        );
+        assert_eq!(
+            Err(SnailfishParseError::InvalidChar {
+                offset: 3,
+                found: 'x',
+                expected: NUMBER_START,
+            }),
+            "[1,x]".parse::<SnailfishNumber>()
+        );
+        assert_eq!(
+            Err(SnailfishParseError::InvalidChar {
+                offset: 7,
+                found: ']',
+                expected: &[Token::Comma],
+            }),
+            "[[1,2] ]".parse::<SnailfishNumber>()
+        );
+        assert_eq!(
+            Err(SnailfishParseError::InvalidChar {
+                offset: 6,
+                found: '[',
+                expected: &[Token::End],
+            }),
+            "[1,2] [3,4]".parse::<SnailfishNumber>()
+        );
+        assert_eq!(
+            Err(SnailfishParseError::InvalidChar {
+                offset: 1,
+                found: ',',
+                expected: &[Token::Digit, Token::End],
+            }),
+            "5,".parse::<SnailfishNumber>()
+        );
+
+        let e = "[1;2]".parse::<SnailfishNumber>().unwrap_err();
+        assert_eq!(
+            "Invalid character ';' at offset 2, expected a digit or ','",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for line in include_str!("./sample.txt").lines() {
+            let n = line.parse::<SnailfishNumber>().unwrap();
+            assert_eq!(line, n.to_string());
+            assert_eq!(n, n.to_string().parse::<SnailfishNumber>().unwrap());
+        }
+
+        let n = "[ [1, 2],  30 ]".parse::<SnailfishNumber>().unwrap();
+        assert_eq!("[[1,2],30]", n.to_string());
+    }
+
+    #[test]
+    fn test_display_pretty() {
+        let n = "[[[[[9,8],1],2],3],4]".parse::<SnailfishNumber>().unwrap();
+        let expected = "0 [
+1   [
+2     [
+3       [
+4         [9,8]  <- explodes
+4         1
+3       ]
+3       2
+2     ]
+2     3
+1   ]
+1   4
+0 ]
+";
+        assert_eq!(expected, n.pretty(&SnailfishRules::default()).to_string());
+
+        let rules = SnailfishRules {
+            explode_depth: 2,
+            ..SnailfishRules::default()
+        };
+        let expected = "0 [
+1   [
+2     [[[9,8],1],2]  <- explodes
+2     3
+1   ]
+1   4
+0 ]
+";
+        assert_eq!(expected, n.pretty(&rules).to_string());
+    }
+
     #[test]
     fn test_reduce() {
+        let rules = SnailfishRules::default();
         let unreduced = "[[[[[9,8],1],2],3],4]".parse::<SnailfishNumber>().unwrap();
         let reduced = "[[[[0,9],2],3],4]".parse::<SnailfishNumber>().unwrap();
-        assert_eq!(reduced, reduce(unreduced));
+        assert_eq!(reduced, reduce(unreduced, &rules));
 
         let unreduced = "[7,[6,[5,[4,[3,2]]]]]".parse::<SnailfishNumber>().unwrap();
         let reduced = "[7,[6,[5,[7,0]]]]".parse::<SnailfishNumber>().unwrap();
-        assert_eq!(reduced, reduce(unreduced));
+        assert_eq!(reduced, reduce(unreduced, &rules));
 
         let unreduced = "[[6,[5,[4,[3,2]]]],1]".parse::<SnailfishNumber>().unwrap();
         let reduced = "[[6,[5,[7,0]]],3]".parse::<SnailfishNumber>().unwrap();
-        assert_eq!(reduced, reduce(unreduced));
+        assert_eq!(reduced, reduce(unreduced, &rules));
 
         let unreduced = "[[3,[2,[1,[7,3]]]],[6,[5,[4,[3,2]]]]]"
             .parse::<SnailfishNumber>()
@@ -278,19 +788,83 @@ mod tests {
         let reduced = "[[3,[2,[8,0]]],[9,[5,[7,0]]]]"
             .parse::<SnailfishNumber>()
             .unwrap();
-        assert_eq!(reduced, reduce(unreduced));
+        assert_eq!(reduced, reduce(unreduced, &rules));
 
         let unreduced = "[10, 11]".parse::<SnailfishNumber>().unwrap();
         let reduced = "[[5,5],[5,6]]".parse::<SnailfishNumber>().unwrap();
-        assert_eq!(reduced, reduce(unreduced));
+        assert_eq!(reduced, reduce(unreduced, &rules));
 
         let unreduced = "[1, [2, 13]]".parse::<SnailfishNumber>().unwrap();
         let reduced = "[1, [2,[6,7]]]".parse::<SnailfishNumber>().unwrap();
-        assert_eq!(reduced, reduce(unreduced));
+        assert_eq!(reduced, reduce(unreduced, &rules));
+    }
+
+    #[test]
+    fn test_reduce_steps() {
+        let rules = SnailfishRules::default();
+        let unreduced = "[[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]"
+            .parse::<SnailfishNumber>()
+            .unwrap();
+
+        let steps = reduce_steps(unreduced.clone(), &rules)
+            .map(|(action, n)| (action.to_string(), n.to_string()))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            ("explode at LLLL", "[[[[0,7],4],[7,[[8,4],9]]],[1,1]]"),
+            ("explode at LRRL", "[[[[0,7],4],[15,[0,13]]],[1,1]]"),
+            ("split at LRL", "[[[[0,7],4],[[7,8],[0,13]]],[1,1]]"),
+            ("split at LRRR", "[[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]"),
+            ("explode at LRRR", "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]"),
+        ];
+        assert_eq!(
+            expected
+                .into_iter()
+                .map(|(a, n)| (a.to_string(), n.to_string()))
+                .collect::<Vec<_>>(),
+            steps
+        );
+
+        let last = reduce_steps(unreduced.clone(), &rules).last().unwrap().1;
+        assert_eq!(reduce(unreduced, &rules), last);
+
+        let reduced = "[[1,2],3]".parse::<SnailfishNumber>().unwrap();
+        assert_eq!(0, reduce_steps(reduced, &rules).count());
+    }
+
+    #[test]
+    fn test_variant_rules() {
+        let rules = SnailfishRules {
+            explode_depth: 3,
+            ..SnailfishRules::default()
+        };
+        let unreduced = "[[[[1,2],3],4],5]".parse::<SnailfishNumber>().unwrap();
+        let reduced = "[[[0,5],4],5]".parse::<SnailfishNumber>().unwrap();
+        assert_eq!(reduced, reduce(unreduced, &rules));
+
+        let rules = SnailfishRules {
+            split_threshold: 5,
+            ..SnailfishRules::default()
+        };
+        let unreduced = "[6,1]".parse::<SnailfishNumber>().unwrap();
+        let reduced = "[[3,3],1]".parse::<SnailfishNumber>().unwrap();
+        assert_eq!(reduced, reduce(unreduced.clone(), &rules));
+        assert_eq!(
+            unreduced,
+            reduce(unreduced.clone(), &SnailfishRules::default())
+        );
+
+        let rules = SnailfishRules {
+            left_weight: 1,
+            right_weight: 10,
+            ..SnailfishRules::default()
+        };
+        let num = "[[1,2],3]".parse::<SnailfishNumber>().unwrap();
+        assert_eq!(51, magnitude(&num, &rules));
     }
 
     #[test]
     fn test_add() {
+        let rules = SnailfishRules::default();
         let a = "[[[[4,3],4],4],[7,[[8,4],9]]]"
             .parse::<SnailfishNumber>()
             .unwrap();
@@ -300,7 +874,7 @@ mod tests {
             .parse::<SnailfishNumber>()
             .unwrap();
 
-        assert_eq!(result, add(a, b));
+        assert_eq!(result, add(a, b, &rules));
 
         let input = "[[[0,[4,5]],[0,0]],[[[4,5],[2,6]],[9,5]]]
 [7,[[[3,7],[4,3]],[[6,3],[8,8]]]]
@@ -320,15 +894,19 @@ mod tests {
         let result = "[[[[8,7],[7,7]],[[8,6],[7,7]]],[[[0,7],[6,6]],[8,7]]]"
             .parse::<SnailfishNumber>()
             .unwrap();
-        assert_eq!(result, nums.into_iter().reduce(|a, b| add(a, b)).unwrap());
+        assert_eq!(
+            result,
+            nums.into_iter().reduce(|a, b| add(a, b, &rules)).unwrap()
+        );
     }
 
     #[test]
     fn test_magnitude() {
+        let rules = SnailfishRules::default();
         let num = "[[[[6,6],[7,6]],[[7,7],[7,0]]],[[[7,7],[7,7]],[[7,8],[9,9]]]]"
             .parse::<SnailfishNumber>()
             .unwrap();
 
-        assert_eq!(4140, magnitude(&num));
+        assert_eq!(4140, magnitude(&num, &rules));
     }
 }
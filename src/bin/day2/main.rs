@@ -1,68 +1,524 @@
 use std::error::Error;
-use std::str::FromStr;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 enum Command {
-    Forward(i32),
-    Down(i32),
-    Up(i32),
+    Forward(i64),
+    Down(i64),
+    Up(i64),
+    Back(i64),
+    // Back to the surface at the starting point, with no aim
+    Reset,
+    Repeat(usize, Vec<Command>),
 }
 
 use Command::*;
 
-impl FromStr for Command {
-    type Err = Box<dyn Error>;
+// The same syntax the parser reads
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Forward(i) => write!(f, "forward {}", i),
+            Down(i) => write!(f, "down {}", i),
+            Up(i) => write!(f, "up {}", i),
+            Back(i) => write!(f, "back {}", i),
+            Reset => write!(f, "reset"),
+            Repeat(times, body) => {
+                write!(f, "repeat {} {{", times)?;
+                for c in body {
+                    write!(f, " {}", c)?;
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let components = s.trim().split_ascii_whitespace().collect::<Vec<_>>();
-        if components.len() != 2 {
-            Err("Improper command format")?;
+#[derive(Debug, PartialEq)]
+enum ParseErrorKind {
+    UnknownCommand(String),
+    ExpectedNumber {
+        after: String,
+        found: Option<String>,
+    },
+    ExpectedOpenBrace {
+        found: Option<String>,
+    },
+    UnclosedRepeat,
+    UnmatchedCloseBrace,
+}
+
+// Lines and columns count from 1
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    line: usize,
+    column: usize,
+    kind: ParseErrorKind,
+}
+
+impl Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}, column {}: ", self.line, self.column)?;
+
+        let found = |found: &Option<String>| match found {
+            Some(token) => format!("{:?}", token),
+            None => "the end of the input".to_string(),
+        };
+        match &self.kind {
+            ParseErrorKind::UnknownCommand(word) => write!(
+                f,
+                "Unknown command {:?}, expected forward, back, down, up, reset or repeat",
+                word
+            ),
+            ParseErrorKind::ExpectedNumber {
+                after,
+                found: token,
+            } => {
+                write!(
+                    f,
+                    "Expected a number after {:?}, found {}",
+                    after,
+                    found(token)
+                )
+            }
+            ParseErrorKind::ExpectedOpenBrace { found: token } => {
+                write!(
+                    f,
+                    "Expected '{{' to start the repeated commands, found {}",
+                    found(token)
+                )
+            }
+            ParseErrorKind::UnclosedRepeat => write!(f, "This repeat block is never closed"),
+            ParseErrorKind::UnmatchedCloseBrace => write!(f, "'}}' without a repeat to close"),
         }
+    }
+}
+
+// A word of the input, or a brace (which needs no whitespace around it)
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+fn tokenize(input: &str) -> (Vec<Token<'_>>, (usize, usize)) {
+    let mut tokens = Vec::new();
+    let mut end = (1, 1);
+
+    for (i, line) in input.lines().enumerate() {
+        let mut start: Option<(usize, usize)> = None;
+        let mut column = 0;
+        for (byte, c) in line.char_indices() {
+            column += 1;
+            if c.is_whitespace() || c == '{' || c == '}' {
+                if let Some((start_byte, start_column)) = start.take() {
+                    tokens.push(Token {
+                        text: &line[start_byte..byte],
+                        line: i + 1,
+                        column: start_column,
+                    });
+                }
+                if !c.is_whitespace() {
+                    tokens.push(Token {
+                        text: &line[byte..byte + 1],
+                        line: i + 1,
+                        column,
+                    });
+                }
+            } else if start.is_none() {
+                start = Some((byte, column));
+            }
+        }
+        if let Some((start_byte, start_column)) = start {
+            tokens.push(Token {
+                text: &line[start_byte..],
+                line: i + 1,
+                column: start_column,
+            });
+        }
+        end = (i + 1, column + 1);
+    }
+
+    (tokens, end)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    // Where errors about running out of input point to
+    end: (usize, usize),
+}
+
+impl<'a> Parser<'a> {
+    fn error_at(&self, token: Option<&Token>, kind: ParseErrorKind) -> ParseError {
+        let (line, column) = token.map_or(self.end, |t| (t.line, t.column));
+        ParseError { line, column, kind }
+    }
+
+    fn next(&mut self) -> Option<&Token<'a>> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, after: &str) -> Result<T, ParseError> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        match token.map(|t| t.text.parse::<T>()) {
+            Some(Ok(n)) => Ok(n),
+            _ => Err(self.error_at(
+                token,
+                ParseErrorKind::ExpectedNumber {
+                    after: after.to_string(),
+                    found: token.map(|t| t.text.to_string()),
+                },
+            )),
+        }
+    }
+
+    // Commands up to the end of the input, or up to the '}' closing the
+    // repeat at `opened_by`
+    fn commands(&mut self, opened_by: Option<(usize, usize)>) -> Result<Vec<Command>, ParseError> {
+        let mut commands = Vec::new();
 
-        let num_units = components[1].parse::<i32>()?;
-        match components[0] {
-            "forward" => Ok(Forward(num_units)),
-            "up" => Ok(Up(num_units)),
-            "down" => Ok(Down(num_units)),
-            _ => Err(format!("Unrecognized direction: {}", components[0]).into()),
+        loop {
+            let (text, line, column) = match self.next() {
+                Some(t) => (t.text, t.line, t.column),
+                None => {
+                    return match opened_by {
+                        Some((line, column)) => Err(ParseError {
+                            line,
+                            column,
+                            kind: ParseErrorKind::UnclosedRepeat,
+                        }),
+                        None => Ok(commands),
+                    }
+                }
+            };
+
+            let command = match text {
+                "}" if opened_by.is_some() => return Ok(commands),
+                "}" => {
+                    return Err(ParseError {
+                        line,
+                        column,
+                        kind: ParseErrorKind::UnmatchedCloseBrace,
+                    })
+                }
+                "forward" => Forward(self.number(text)?),
+                "back" => Back(self.number(text)?),
+                "down" => Down(self.number(text)?),
+                "up" => Up(self.number(text)?),
+                "reset" => Reset,
+                "repeat" => {
+                    let times = self.number(text)?;
+                    let brace = self.tokens.get(self.pos);
+                    self.pos += 1;
+                    if brace.is_none_or(|t| t.text != "{") {
+                        return Err(self.error_at(
+                            brace,
+                            ParseErrorKind::ExpectedOpenBrace {
+                                found: brace.map(|t| t.text.to_string()),
+                            },
+                        ));
+                    }
+                    Repeat(times, self.commands(Some((line, column)))?)
+                }
+                _ => {
+                    return Err(ParseError {
+                        line,
+                        column,
+                        kind: ParseErrorKind::UnknownCommand(text.to_string()),
+                    })
+                }
+            };
+            commands.push(command);
         }
     }
 }
 
-fn parse_input(input: &str) -> Result<Vec<Command>, Box<dyn Error>> {
-    input.lines().map(|l| l.parse::<Command>()).collect()
+// Commands are separated by any whitespace, so a repeat block can sit on one
+// line or spread over several:
+//
+//     repeat 3 { forward 2 down 1 }
+fn parse_input(input: &str) -> Result<Vec<Command>, ParseError> {
+    let (tokens, end) = tokenize(input);
+    Parser {
+        tokens,
+        pos: 0,
+        end,
+    }
+    .commands(None)
 }
 
-fn part1(commands: &[Command]) -> (i32, i32) {
-    commands
-        .iter()
-        .fold((0, 0), |(depth, horiz_pos), c| match c {
-            Up(i) => (depth - i, horiz_pos),
-            Down(i) => (depth + i, horiz_pos),
-            Forward(i) => (depth, horiz_pos + i),
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Submarine {
+    depth: i64,
+    horiz_pos: i64,
+    aim: i64,
+}
+
+// How the submarine responds to the two kinds of movement. `up` is `down`
+// with the amount negated, and `back` is `forward` negated. None if the new
+// state doesn't fit in 64 bits.
+trait MovementModel {
+    fn down(&self, sub: Submarine, x: i64) -> Option<Submarine>;
+    fn forward(&self, sub: Submarine, x: i64) -> Option<Submarine>;
+}
+
+// Part 1: down and up change the depth directly
+struct Direct;
+
+impl MovementModel for Direct {
+    fn down(&self, sub: Submarine, x: i64) -> Option<Submarine> {
+        Some(Submarine {
+            depth: sub.depth.checked_add(x)?,
+            ..sub
         })
+    }
+
+    fn forward(&self, sub: Submarine, x: i64) -> Option<Submarine> {
+        Some(Submarine {
+            horiz_pos: sub.horiz_pos.checked_add(x)?,
+            ..sub
+        })
+    }
+}
+
+// Part 2: down and up change the aim, and moving forward follows it
+struct Aimed;
+
+impl MovementModel for Aimed {
+    fn down(&self, sub: Submarine, x: i64) -> Option<Submarine> {
+        Some(Submarine {
+            aim: sub.aim.checked_add(x)?,
+            ..sub
+        })
+    }
+
+    fn forward(&self, sub: Submarine, x: i64) -> Option<Submarine> {
+        Some(Submarine {
+            depth: sub.depth.checked_add(sub.aim.checked_mul(x)?)?,
+            horiz_pos: sub.horiz_pos.checked_add(x)?,
+            aim: sub.aim,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct OverflowError {
+    step: usize,
+    command: String,
+}
+
+impl Error for OverflowError {}
+
+impl fmt::Display for OverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Step {} ({}) takes the submarine past what 64 bits can hold",
+            self.step, self.command
+        )
+    }
+}
+
+impl Submarine {
+    // Repeat blocks are unrolled by `Course`, so only ever get here one
+    // command at a time
+    fn execute(self, model: &dyn MovementModel, command: &Command) -> Option<Submarine> {
+        match command {
+            Forward(i) => model.forward(self, *i),
+            Back(i) => model.forward(self, i.checked_neg()?),
+            Down(i) => model.down(self, *i),
+            Up(i) => model.down(self, i.checked_neg()?),
+            Reset => Some(Submarine::default()),
+            Repeat(..) => unreachable!("repeat blocks are unrolled by Course"),
+        }
+    }
+
+    fn run(model: &dyn MovementModel, commands: &[Command]) -> Result<Submarine, OverflowError> {
+        let mut sub = Submarine::default();
+        for step in Course::new(model, commands) {
+            sub = step?.1;
+        }
+        Ok(sub)
+    }
 }
 
-fn part2(commands: &[Command]) -> (i32, i32) {
-    let (depth, horiz_pos, _aim) = commands
-        .iter()
-        .fold((0, 0, 0), |(depth, horiz_pos, aim), c| match c {
-            Up(i) => (depth, horiz_pos, aim - i),
-            Down(i) => (depth, horiz_pos, aim + i),
-            Forward(i) => (depth + aim * i, horiz_pos + i, aim),
-        });
-    (depth, horiz_pos)
+// A block of commands being worked through, `passes_left` more times after
+// this one
+struct Frame<'a> {
+    commands: &'a [Command],
+    next: usize,
+    passes_left: usize,
+}
+
+// The state of the submarine after each command, with repeat blocks unrolled
+// into the commands they repeat
+struct Course<'a> {
+    model: &'a dyn MovementModel,
+    sub: Submarine,
+    steps: usize,
+    stack: Vec<Frame<'a>>,
+}
+
+impl<'a> Course<'a> {
+    fn new(model: &'a dyn MovementModel, commands: &'a [Command]) -> Course<'a> {
+        Course {
+            model,
+            sub: Submarine::default(),
+            steps: 0,
+            stack: vec![Frame {
+                commands,
+                next: 0,
+                passes_left: 0,
+            }],
+        }
+    }
+}
+
+impl<'a> Iterator for Course<'a> {
+    // The course ends after the first error
+    type Item = Result<(&'a Command, Submarine), OverflowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = self.stack.last_mut()?;
+            if frame.next == frame.commands.len() {
+                if frame.passes_left > 0 {
+                    frame.passes_left -= 1;
+                    frame.next = 0;
+                } else {
+                    self.stack.pop();
+                }
+                continue;
+            }
+
+            let command = &frame.commands[frame.next];
+            frame.next += 1;
+            match command {
+                Repeat(times, body) => {
+                    if *times > 0 && !body.is_empty() {
+                        self.stack.push(Frame {
+                            commands: body,
+                            next: 0,
+                            passes_left: times - 1,
+                        });
+                    }
+                }
+                _ => {
+                    self.steps += 1;
+                    return match self.sub.execute(self.model, command) {
+                        Some(sub) => {
+                            self.sub = sub;
+                            Some(Ok((command, sub)))
+                        }
+                        None => {
+                            self.stack.clear();
+                            Some(Err(OverflowError {
+                                step: self.steps,
+                                command: command.to_string(),
+                            }))
+                        }
+                    };
+                }
+            }
+        }
+    }
+}
+
+// The first step (counting from 1) after which the submarine is above the
+// surface, and the command that took it there
+fn first_surfacing<'a>(
+    course: Course<'a>,
+) -> Result<Option<(usize, &'a Command, Submarine)>, OverflowError> {
+    for (i, step) in course.enumerate() {
+        let (command, sub) = step?;
+        if sub.depth < 0 {
+            return Ok(Some((i + 1, command, sub)));
+        }
+    }
+    Ok(None)
+}
+
+// One row per step, with the state under both models side by side
+fn write_csv<W: Write>(mut out: W, commands: &[Command]) -> Result<(), Box<dyn Error>> {
+    writeln!(
+        out,
+        "step,command,direct_depth,direct_horiz_pos,aimed_depth,aimed_horiz_pos,aim"
+    )?;
+    let courses = Course::new(&Direct, commands).zip(Course::new(&Aimed, commands));
+    for (i, (direct, aimed)) in courses.enumerate() {
+        let ((command, direct), (_, aimed)) = (direct?, aimed?);
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            i + 1,
+            command,
+            direct.depth,
+            direct.horiz_pos,
+            aimed.depth,
+            aimed.horiz_pos,
+            aimed.aim
+        )?;
+    }
+    Ok(out.flush()?)
+}
+
+fn part1(commands: &[Command]) -> Result<(i64, i64), OverflowError> {
+    let sub = Submarine::run(&Direct, commands)?;
+    Ok((sub.depth, sub.horiz_pos))
+}
+
+fn part2(commands: &[Command]) -> Result<(i64, i64), OverflowError> {
+    let sub = Submarine::run(&Aimed, commands)?;
+    Ok((sub.depth, sub.horiz_pos))
+}
+
+fn answer((depth, horiz_pos): (i64, i64)) -> Result<i64, String> {
+    depth.checked_mul(horiz_pos).ok_or(format!(
+        "Depth {} times position {} doesn't fit in 64 bits",
+        depth, horiz_pos
+    ))
+}
+
+// Warns if the course takes the submarine above the surface. Overflowing
+// only gets a warning here, as the answer for the model reports it.
+fn warn_if_surfacing(name: &str, model: &dyn MovementModel, commands: &[Command]) {
+    match first_surfacing(Course::new(model, commands)) {
+        Ok(Some((step, command, sub))) => eprintln!(
+            "Warning: with the {} model, step {} ({}) takes the submarine to depth {}, above the surface",
+            name, step, command, sub.depth
+        ),
+        Ok(None) => (),
+        Err(e) => eprintln!(
+            "Warning: with the {} model, the course can't be checked for surfacing: {}",
+            name, e
+        ),
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let input = aoc2021::get_input_string()?;
-    let commands = parse_input(&input)?;
+    let commands = parse_input(&input).map_err(|e| e.to_string())?;
+
+    if let Some(path) = aoc2021::get_flag_value("csv") {
+        let file = File::create(&path).map_err(|e| format!("{}: {}", path, e))?;
+        write_csv(BufWriter::new(file), &commands).map_err(|e| format!("{}: {}", path, e))?;
+    }
 
-    let part1 = part1(commands.as_slice());
-    println!("Part 1: {}", part1.0 * part1.1);
+    warn_if_surfacing("direct", &Direct, &commands);
+    let part1 = part1(commands.as_slice()).map_err(|e| format!("Part 1: {}", e))?;
+    println!("Part 1: {}", answer(part1)?);
 
-    let part2 = part2(commands.as_slice());
-    println!("Part 1: {}", part2.0 * part2.1);
+    warn_if_surfacing("aimed", &Aimed, &commands);
+    let part2 = part2(commands.as_slice()).map_err(|e| format!("Part 2: {}", e))?;
+    println!("Part 2: {}", answer(part2)?);
 
     Ok(())
 }
@@ -71,7 +527,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 mod tests {
     use super::*;
 
-    const TEST_COMMANDS: &'static [Command] =
+    const TEST_COMMANDS: &[Command] =
         &[Forward(5), Down(5), Forward(8), Up(3), Down(8), Forward(2)];
 
     #[test]
@@ -90,13 +546,251 @@ forward 2
 
     #[test]
     fn test_part1() {
-        let (depth, horiz_pos) = part1(TEST_COMMANDS);
+        let (depth, horiz_pos) = part1(TEST_COMMANDS).unwrap();
         assert_eq!((depth, horiz_pos), (10, 15));
     }
 
     #[test]
     fn test_part2() {
-        let (depth, horiz_pos) = part2(TEST_COMMANDS);
+        let (depth, horiz_pos) = part2(TEST_COMMANDS).unwrap();
         assert_eq!((depth, horiz_pos), (60, 15));
     }
+
+    #[test]
+    fn test_extended_commands() {
+        let input = "down 2
+repeat 3 {
+    forward 4 back 1
+    repeat 2 { up 1 down 2 }
+}
+";
+        let commands = parse_input(input).unwrap();
+        assert_eq!(
+            vec![
+                Down(2),
+                Repeat(
+                    3,
+                    vec![Forward(4), Back(1), Repeat(2, vec![Up(1), Down(2)])]
+                ),
+            ],
+            commands
+        );
+
+        // Each pass nets 3 forward and 2 down
+        assert_eq!((8, 9), part1(&commands).unwrap());
+        assert_eq!(
+            Submarine {
+                depth: 2 * 3 + 4 * 3 + 6 * 3,
+                horiz_pos: 9,
+                aim: 8
+            },
+            Submarine::run(&Aimed, &commands).unwrap()
+        );
+
+        let commands = parse_input("forward 5 down 3 reset forward 1 repeat 0 { down 9 }").unwrap();
+        assert_eq!((0, 1), part1(&commands).unwrap());
+        assert_eq!((0, 1), part2(&commands).unwrap());
+
+        // Braces don't need spaces around them
+        assert_eq!(
+            vec![Repeat(2, vec![Up(1)])],
+            parse_input("repeat 2{up 1}").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |input: &str| {
+            let e = parse_input(input).unwrap_err();
+            (e.line, e.column, e.kind)
+        };
+
+        assert_eq!(
+            (2, 3, ParseErrorKind::UnknownCommand("sideways".into())),
+            error("forward 1\n  sideways 4")
+        );
+        assert_eq!(
+            (
+                1,
+                9,
+                ParseErrorKind::ExpectedNumber {
+                    after: "forward".into(),
+                    found: Some("x".into())
+                }
+            ),
+            error("forward x")
+        );
+        assert_eq!(
+            (
+                1,
+                4,
+                ParseErrorKind::ExpectedNumber {
+                    after: "up".into(),
+                    found: None
+                }
+            ),
+            error("up ")
+        );
+        assert_eq!(
+            (
+                1,
+                10,
+                ParseErrorKind::ExpectedOpenBrace {
+                    found: Some("up".into())
+                }
+            ),
+            error("repeat 2 up 1 }")
+        );
+        // Columns count characters, not bytes
+        assert_eq!(
+            (1, 8, ParseErrorKind::UnknownCommand("\u{e9}".into())),
+            error("down 1\t\u{e9} 2")
+        );
+        assert_eq!(
+            (2, 1, ParseErrorKind::UnclosedRepeat),
+            error("up 1\nrepeat 2 {\n up 1")
+        );
+        assert_eq!((1, 6, ParseErrorKind::UnmatchedCloseBrace), error("up 1 }"));
+
+        assert_eq!(
+            "Line 2, column 3: Unknown command \"sideways\", expected forward, back, down, up, reset or repeat",
+            parse_input("forward 1\n  sideways 4").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_course() {
+        let states = Course::new(&Aimed, TEST_COMMANDS)
+            .map(|step| step.unwrap().1)
+            .map(|sub| (sub.depth, sub.horiz_pos, sub.aim))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (0, 5, 0),
+                (0, 5, 5),
+                (40, 13, 5),
+                (40, 13, 2),
+                (40, 13, 10),
+                (60, 15, 10)
+            ],
+            states
+        );
+
+        // Repeats are unrolled, and empty ones skipped
+        let commands =
+            parse_input("repeat 0 { up 1 } repeat 2 { down 1 repeat 3 { } forward 2 }").unwrap();
+        let steps = Course::new(&Direct, &commands)
+            .map(|step| step.unwrap())
+            .map(|(c, sub)| (c.to_string(), sub.depth, sub.horiz_pos))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("down 1".to_string(), 1, 0),
+                ("forward 2".to_string(), 1, 2),
+                ("down 1".to_string(), 2, 2),
+                ("forward 2".to_string(), 2, 4),
+            ],
+            steps
+        );
+
+        assert!(Course::new(&Aimed, &[]).next().is_none());
+        assert_eq!(Submarine::default(), Submarine::run(&Aimed, &[]).unwrap());
+    }
+
+    #[test]
+    fn test_surfacing() {
+        assert_eq!(
+            None,
+            first_surfacing(Course::new(&Direct, TEST_COMMANDS)).unwrap()
+        );
+        assert_eq!(
+            None,
+            first_surfacing(Course::new(&Aimed, TEST_COMMANDS)).unwrap()
+        );
+
+        // Going up 2 only surfaces under the direct model; under the aimed
+        // model it tilts the nose up, which surfaces on the next forward
+        let commands = parse_input("down 1 forward 1 up 2 forward 2").unwrap();
+        let (step, command, sub) = first_surfacing(Course::new(&Direct, &commands))
+            .unwrap()
+            .unwrap();
+        assert_eq!((3, &Up(2), -1), (step, command, sub.depth));
+        let (step, command, sub) = first_surfacing(Course::new(&Aimed, &commands))
+            .unwrap()
+            .unwrap();
+        assert_eq!((4, &Forward(2), -1), (step, command, sub.depth));
+    }
+
+    #[test]
+    fn test_csv() {
+        let commands = parse_input("repeat 2 { forward 5 down 5 }").unwrap();
+        let mut out = Vec::new();
+        write_csv(&mut out, &commands).unwrap();
+        assert_eq!(
+            "step,command,direct_depth,direct_horiz_pos,aimed_depth,aimed_horiz_pos,aim
+1,forward 5,0,5,0,5,0
+2,down 5,5,5,0,5,5
+3,forward 5,5,10,25,10,5
+4,down 5,10,10,25,10,10
+",
+            String::from_utf8(out).unwrap()
+        );
+
+        assert_eq!(
+            "repeat 2 { forward 5 repeat 1 { reset } }",
+            parse_input("repeat 2 {forward 5 repeat 1 {reset}}").unwrap()[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_long_course() {
+        // Going down 1000 and forward 1000 a hundred thousand times ends up
+        // far deeper than an i32 can hold: the aim after pass k is 1000k, so
+        // the depth is 1000 * 1000 * (1 + 2 + ... + 100000)
+        let input = "down 1000\nforward 1000\n".repeat(100_000);
+        let commands = parse_input(&input).unwrap();
+
+        assert_eq!((100_000_000, 100_000_000), part1(&commands).unwrap());
+        let (depth, horiz_pos) = part2(&commands).unwrap();
+        assert_eq!(1_000_000 * (100_000 * 100_001 / 2), depth);
+        assert!(depth > i32::MAX as i64);
+        assert_eq!(100_000_000, horiz_pos);
+        assert_eq!(Ok(5_000_050_000_000_000_000), answer((depth, 1000)));
+        assert!(answer((depth, horiz_pos)).is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let commands = parse_input("repeat 1000000 { down 1000000 forward 1000000 }").unwrap();
+        assert_eq!(
+            (1_000_000_000_000, 1_000_000_000_000),
+            part1(&commands).unwrap()
+        );
+
+        // Depth passes 2^63 once the sum of 10^12 * k over the passes does,
+        // which happens at pass 4295
+        let err = part2(&commands).unwrap_err();
+        assert_eq!(
+            OverflowError {
+                step: 4295 * 2,
+                command: "forward 1000000".into()
+            },
+            err
+        );
+        assert_eq!(
+            "Step 8590 (forward 1000000) takes the submarine past what 64 bits can hold",
+            err.to_string()
+        );
+
+        let commands = parse_input("up -9223372036854775808").unwrap();
+        assert_eq!(1, part1(&commands).unwrap_err().step);
+        let commands = parse_input("forward 9223372036854775807 forward 1").unwrap();
+        assert_eq!(2, part2(&commands).unwrap_err().step);
+
+        // The course stops at the error
+        let commands = parse_input("down 9223372036854775807 down 1 down 1").unwrap();
+        let steps = Course::new(&Direct, &commands).collect::<Vec<_>>();
+        assert_eq!(2, steps.len());
+        assert!(steps[1].is_err());
+    }
 }
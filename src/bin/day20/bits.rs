@@ -0,0 +1,213 @@
+use aoc2021::packed::{mask_tail, words_for};
+
+// An image whose rows are packed 64 pixels to a word, column c of a row being
+// bit c % 64 of word c / 64. Bits past `width` are always 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitImage {
+    width: usize,
+    rows: Vec<Vec<u64>>,
+    fill: u8,
+}
+
+fn set_bit(row: &mut [u64], c: usize) {
+    row[c >> 6] |= 1 << (c & 63);
+}
+
+fn fill_word(fill: u8) -> u64 {
+    if fill == 1 {
+        !0
+    } else {
+        0
+    }
+}
+
+// `row`, `width` pixels wide, moved two columns along with two columns of
+// background added on either side, written into `out`
+fn pad(row: &[u64], width: usize, fill: u8, out: &mut [u64]) {
+    let mut carry = fill_word(fill) >> 62;
+    for (i, out_word) in out.iter_mut().enumerate() {
+        let word = row.get(i).copied().unwrap_or(0);
+        *out_word = (word << 2) | carry;
+        carry = word >> 62;
+    }
+    if fill == 1 {
+        set_bit(out, width + 2);
+        set_bit(out, width + 3);
+    }
+    mask_tail(out, width + 4);
+}
+
+impl BitImage {
+    pub fn from_rows(img: &[Vec<u8>], fill: u8) -> BitImage {
+        let width = img.first().map_or(0, |row| row.len());
+        let rows = img
+            .iter()
+            .map(|row| {
+                let mut packed = vec![0u64; words_for(width)];
+                for (c, px) in row.iter().enumerate() {
+                    if *px == 1 {
+                        set_bit(&mut packed, c);
+                    }
+                }
+                packed
+            })
+            .collect();
+
+        BitImage { width, rows, fill }
+    }
+
+    // Lit pixels inside the image, not counting the infinite background
+    pub fn num_lit(&self) -> usize {
+        self.rows
+            .iter()
+            .flatten()
+            .map(|w| w.count_ones() as usize)
+            .sum()
+    }
+
+    // Row `r` with two columns of background on either side, written into
+    // `out`. Rows outside the image are all background.
+    fn padded_row(&self, r: isize, out: &mut [u64]) {
+        match usize::try_from(r).ok().and_then(|r| self.rows.get(r)) {
+            Some(row) => pad(row, self.width, self.fill, out),
+            None => {
+                out.fill(fill_word(self.fill));
+                mask_tail(out, self.width + 4);
+            }
+        }
+    }
+
+    // Equivalent to `enhance` in main.rs: the result is one pixel bigger on
+    // every side. The three source rows an output row needs slide down the
+    // image one row at a time, and an output word's 64 windows are all read
+    // from the same 128 bits of each of them.
+    pub fn enhance(&self, alg: &[u8]) -> BitImage {
+        let new_width = self.width + 2;
+        let new_height = self.rows.len() + 2;
+        let padded_words = words_for(self.width + 4);
+
+        // The windows below come out with the top left pixel as the lowest
+        // bit and the bottom right as the highest, the reverse of `alg`'s order
+        let table = (0..512usize)
+            .map(|idx| alg[idx.reverse_bits() >> (usize::BITS - 9)])
+            .collect::<Vec<_>>();
+
+        // Output row r is centred on source row r - 1, so it needs source
+        // rows r - 2 to r
+        let mut window = [
+            vec![0u64; padded_words],
+            vec![0u64; padded_words],
+            vec![0u64; padded_words],
+        ];
+        self.padded_row(-2, &mut window[1]);
+        self.padded_row(-1, &mut window[2]);
+        let mut rows = Vec::with_capacity(new_height);
+
+        for r in 0..new_height {
+            window.rotate_left(1);
+            self.padded_row(r as isize, &mut window[2]);
+
+            let mut out = vec![0u64; words_for(new_width)];
+            for (j, out_word) in out.iter_mut().enumerate() {
+                // Output column c reads padded columns c to c + 2
+                let [top, mid, bot] = window.each_ref().map(|row| {
+                    row[j] as u128 | (row.get(j + 1).copied().unwrap_or(0) as u128) << 64
+                });
+                for b in 0..64 {
+                    let idx = ((top >> b) & 7) | ((mid >> b) & 7) << 3 | ((bot >> b) & 7) << 6;
+                    *out_word |= (table[idx as usize] as u64) << b;
+                }
+            }
+            mask_tail(&mut out, new_width);
+            rows.push(out);
+        }
+
+        let fill = if self.fill == 1 { alg[511] } else { alg[0] };
+
+        BitImage {
+            width: new_width,
+            rows,
+            fill,
+        }
+    }
+}
+
+pub fn num_lit_after_m_iterations(img: &[Vec<u8>], fill: u8, alg: &[u8], m: usize) -> usize {
+    let mut img = BitImage::from_rows(img, fill);
+    for _ in 0..m {
+        img = img.enhance(alg);
+    }
+    img.num_lit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_input;
+    use aoc2021::automaton::{Automaton, Boundary};
+    use std::time::Instant;
+
+    const SAMPLE_INPUT: &str = include_str!("./sample.txt");
+
+    fn bit(row: &[u64], c: usize) -> usize {
+        ((row[c >> 6] >> (c & 63)) & 1) as usize
+    }
+
+    #[test]
+    fn test_enhance() {
+        let (alg, mat) = parse_input(SAMPLE_INPUT).unwrap();
+
+        let mut automaton = Automaton::new(&mat, Boundary::Infinite(0)).unwrap();
+        crate::enhance(&mut automaton, &alg);
+
+        let enhanced = BitImage::from_rows(&mat, 0).enhance(&alg);
+        let unpacked = enhanced
+            .rows
+            .iter()
+            .flat_map(|row| (0..enhanced.width).map(|c| bit(row, c) as u8))
+            .collect::<Vec<_>>();
+
+        assert_eq!(automaton.background(), Some(enhanced.fill));
+        assert_eq!(automaton.cells(), unpacked);
+    }
+
+    #[test]
+    fn test_agrees_with_dense() {
+        let (alg, mat) = parse_input(SAMPLE_INPUT).unwrap();
+
+        // The 70 and 129 pixel wide images after 33 and 62 steps straddle word
+        // boundaries
+        let blinking = crate::blinking(&alg);
+
+        for alg in [&alg, &blinking] {
+            for m in [1, 2, 5, 33, 62] {
+                assert_eq!(
+                    crate::num_lit_after_m_iterations(&mat, 0, alg, m),
+                    num_lit_after_m_iterations(&mat, 0, alg, m)
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_bits_vs_dense() {
+        let (alg, mat) = parse_input(include_str!("./input")).unwrap();
+
+        for m in [50, 500] {
+            let start = Instant::now();
+            let dense = crate::num_lit_after_m_iterations(&mat, 0, &alg, m);
+            let dense_elapsed = start.elapsed();
+
+            let start = Instant::now();
+            let packed = num_lit_after_m_iterations(&mat, 0, &alg, m);
+            let packed_elapsed = start.elapsed();
+
+            assert_eq!(dense, packed);
+            println!(
+                "{} iterations: dense {:?}, packed {:?}",
+                m, dense_elapsed, packed_elapsed
+            );
+        }
+    }
+}
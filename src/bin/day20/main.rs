@@ -1,100 +1,304 @@
+use aoc2021::automaton::{Automaton, Boundary};
+use aoc2021::visualize::{self, Control, Visualizer};
 use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+mod bits;
+mod netpbm;
+mod sparse;
+
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    MissingAlgorithm,
+    WrongAlgorithmLength(usize),
+    MissingBlankLine,
+    // Lines and columns count from 1
+    InvalidChar {
+        line: usize,
+        column: usize,
+        found: char,
+    },
+    UnevenRows {
+        line: usize,
+    },
+}
 
-fn parse_input(s: &str) -> (Vec<u8>, Vec<Vec<u8>>) {
-    let mut lines = s.lines();
+impl Error for ParseError {}
 
-    let to_u8 = |c| if c == '#' { 1 } else { 0 };
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MissingAlgorithm => write!(f, "Missing enhancement algorithm"),
+            ParseError::WrongAlgorithmLength(len) => {
+                write!(f, "Enhancement algorithm has {} entries, expected 512", len)
+            }
+            ParseError::MissingBlankLine => {
+                write!(f, "Expecting a blank line after the enhancement algorithm")
+            }
+            ParseError::InvalidChar {
+                line,
+                column,
+                found,
+            } => write!(
+                f,
+                "Invalid character {:?} at line {}, column {}, expected '#' or '.'",
+                found, line, column
+            ),
+            ParseError::UnevenRows { line } => {
+                write!(f, "Image row on line {} has a different length", line)
+            }
+        }
+    }
+}
 
-    let alg = lines
-        .next()
-        .unwrap()
-        .chars()
-        .map(to_u8)
-        .collect::<Vec<u8>>();
+fn parse_input(s: &str) -> Result<(Vec<u8>, Vec<Vec<u8>>), ParseError> {
+    let mut lines = s.lines().enumerate();
+
+    let parse_line = |(i, l): (usize, &str)| {
+        l.chars()
+            .enumerate()
+            .map(|(j, c)| match c {
+                '#' => Ok(1),
+                '.' => Ok(0),
+                found => Err(ParseError::InvalidChar {
+                    line: i + 1,
+                    column: j + 1,
+                    found,
+                }),
+            })
+            .collect::<Result<Vec<u8>, _>>()
+    };
 
-    // skip blank line
-    lines.next();
+    let alg = parse_line(lines.next().ok_or(ParseError::MissingAlgorithm)?)?;
+    if alg.len() != 512 {
+        return Err(ParseError::WrongAlgorithmLength(alg.len()));
+    }
 
-    let mat = lines
-        .map(|l| l.chars().map(to_u8).collect::<Vec<u8>>())
-        .collect::<Vec<_>>();
+    if !lines.next().is_some_and(|(_, l)| l.is_empty()) {
+        return Err(ParseError::MissingBlankLine);
+    }
 
-    (alg, mat)
+    let mut mat: Vec<Vec<u8>> = Vec::new();
+    for (i, l) in lines {
+        let row = parse_line((i, l))?;
+        if mat.first().is_some_and(|first| first.len() != row.len()) {
+            return Err(ParseError::UnevenRows { line: i + 1 });
+        }
+        mat.push(row);
+    }
+
+    Ok((alg, mat))
 }
 
-fn enhance(
-    src_img: &Vec<u8>,
-    src_dims: (usize, usize),
-    fill: u8,
-    dst_img: &mut Vec<u8>,
-    alg: &Vec<u8>,
-) -> u8 {
-    let src_n = src_dims.0 as i32;
-    let src_m = src_dims.1 as i32;
-
-    let get_alg_idx = |new_r: i32, new_c: i32| {
-        let mut idx = 0usize;
-        for r in (new_r - 1)..=(new_r + 1) {
-            for c in (new_c - 1)..=(new_c + 1) {
-                let px_val = if (1..=src_n).contains(&r) && (1..=src_m).contains(&c) {
-                    src_img[((r - 1) * src_m + c - 1) as usize]
-                } else {
-                    fill
-                };
-                idx = (idx << 1) | (px_val as usize);
-            }
-        }
-        idx
-    };
+// What the infinite background does, starting from dark, as the algorithm
+// is applied over and over. A dark background only looks up `alg[0]` and a
+// lit one `alg[511]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Background {
+    StaysDark,
+    // Lit from the first iteration on
+    StaysLit,
+    // Lit after odd iterations, dark after even ones
+    Blinks,
+}
 
-    for r in 0..(src_n + 2) {
-        for c in 0..(src_m + 2) {
-            let dst_idx = (r * (src_m + 2) + c) as usize;
-            dst_img[dst_idx] = alg[get_alg_idx(r, c) as usize];
+impl Background {
+    fn classify(alg: &[u8]) -> Background {
+        match (alg[0], alg[511]) {
+            (0, _) => Background::StaysDark,
+            (_, 1) => Background::StaysLit,
+            _ => Background::Blinks,
         }
     }
 
-    let fill = if fill == 1 { alg[511] } else { alg[0] };
+    // Whether infinitely many pixels are lit after `m` iterations
+    fn is_lit_after(&self, m: usize) -> bool {
+        match self {
+            Background::StaysDark => false,
+            Background::StaysLit => m > 0,
+            Background::Blinks => m % 2 == 1,
+        }
+    }
+}
 
-    fill
+// The algorithm with its first and last entries swapped, under which a
+// background that starts dark blinks
+#[cfg(test)]
+fn blinking(alg: &[u8]) -> Vec<u8> {
+    let mut blinking = alg.to_vec();
+    blinking.swap(0, 511);
+    blinking
 }
 
-fn num_lit_after_m_iterations(img: &Vec<Vec<u8>>, mut fill: u8, alg: &Vec<u8>, m: usize) -> usize {
-    let src_n = img.len();
-    let src_m = img[0].len();
-    let img = img.iter().flatten().map(|b| *b).collect::<Vec<u8>>();
+// One enhancement of the image and its infinite background
+fn enhance(automaton: &mut Automaton<u8>, alg: &[u8]) {
+    automaton.step(|n| {
+        let idx = n
+            .moore()
+            .iter()
+            .fold(0usize, |idx, px| (idx << 1) | (*px as usize));
+        alg[idx]
+    });
+}
 
-    let new_n = src_n + 2 * m;
-    let new_m = src_m + 2 * m;
+fn num_lit_after_m_iterations(img: &[Vec<u8>], fill: u8, alg: &[u8], m: usize) -> usize {
+    let mut automaton =
+        Automaton::new(img, Boundary::Infinite(fill)).expect("Image rows have different lengths");
 
-    let mut a: Vec<u8> = Vec::with_capacity(new_n * new_m);
-    a.resize(new_n * new_m, 0);
+    for _ in 0..m {
+        enhance(&mut automaton, alg);
+    }
 
-    let mut b = a.clone();
-    a[0..(src_n * src_m)].copy_from_slice(img.as_slice());
+    automaton.cells().iter().map(|b| *b as usize).sum()
+}
 
-    for i in 0..m {
-        let (src, dst): (&mut Vec<u8>, &mut Vec<u8>) = if i % 2 == 0 {
-            (&mut a, &mut b)
+// Writes frame_0000.pbm (the input image) through frame_<m>.pbm to `dir`
+fn write_frames(
+    dir: &Path,
+    img: &[Vec<u8>],
+    alg: &[u8],
+    m: usize,
+    plain: bool,
+) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+
+    let mut automaton = Automaton::new(img, Boundary::Infinite(0))?;
+    for i in 0..=m {
+        if i > 0 {
+            enhance(&mut automaton, alg);
+        }
+        let img = automaton.to_rows();
+        let data = if plain {
+            netpbm::write_plain(&img)
         } else {
-            (&mut b, &mut a)
+            netpbm::write_raw(&img)
         };
-
-        fill = enhance(src, (src_n + 2 * i, src_m + 2 * i), fill, dst, alg);
+        fs::write(dir.join(format!("frame_{:04}.pbm", i)), data)?;
     }
 
-    let last_dst: &Vec<u8> = if (m - 1) % 2 == 0 { &b } else { &a };
+    Ok(())
+}
+
+fn to_str(img: &[Vec<u8>]) -> String {
+    img.iter()
+        .map(|row| {
+            row.iter()
+                .map(|px| if *px == 1 { '#' } else { '.' })
+                .collect()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Shows the image after each of `m` enhancements, or until the user quits
+fn animate(
+    img: &[Vec<u8>],
+    alg: &[u8],
+    m: usize,
+    visualizer: &mut Visualizer,
+) -> Result<(), Box<dyn Error>> {
+    let mut automaton = Automaton::new(img, Boundary::Infinite(0))?;
+
+    for i in 0..=m {
+        if i > 0 {
+            enhance(&mut automaton, alg);
+        }
+        let lit = automaton.cells().iter().filter(|px| **px == 1).count();
+        let title = format!(
+            "Iteration {}: {} lit, background {}",
+            i,
+            lit,
+            if automaton.background() == Some(1) {
+                "lit"
+            } else {
+                "dark"
+            }
+        );
+        if visualizer.show(&title, &to_str(&automaton.to_rows()))? == Control::Quit {
+            break;
+        }
+    }
 
-    last_dst.iter().map(|b| *b as usize).sum()
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    if let Some(path) = aoc2021::get_flag_value("replay") {
+        return visualize::replay(&path);
+    }
+
     let input = aoc2021::get_input_string()?;
 
-    let (alg, img) = parse_input(&input);
+    let (mut alg, mut img) = parse_input(&input).map_err(|e| e.to_string())?;
+    if let Some(path) = aoc2021::get_flag_value("alg") {
+        alg = netpbm::read_alg(&fs::read(&path)?).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(path) = aoc2021::get_flag_value("image") {
+        img = netpbm::read(&fs::read(&path)?).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    let iterations = aoc2021::parse_flag_value::<usize>("iterations")?;
+    if let Some(dir) = aoc2021::get_flag_value("frames") {
+        write_frames(
+            Path::new(&dir),
+            &img,
+            &alg,
+            iterations.unwrap_or(50),
+            aoc2021::has_flag("plain"),
+        )?;
+    }
+    if let Some(mut visualizer) = Visualizer::from_flags()? {
+        animate(&img, &alg, iterations.unwrap_or(50), &mut visualizer)?;
+    }
+
+    let runs = match iterations {
+        Some(m) => vec![(format!("After {} iterations", m), m)],
+        None => vec![("Part 1".to_string(), 2), ("Part 2".to_string(), 50)],
+    };
+    let background = Background::classify(&alg);
+
+    for (label, m) in runs {
+        if background.is_lit_after(m) {
+            eprintln!(
+                "Warning: the background {} (alg[0] = {}, alg[511] = {}), so after {} iterations infinitely many pixels are lit",
+                match background {
+                    Background::StaysLit => "turns lit and stays lit",
+                    _ => "blinks",
+                },
+                alg[0],
+                alg[511],
+                m
+            );
+            println!("{}: infinitely many", label);
+            continue;
+        }
 
-    println!("Part 1: {}", num_lit_after_m_iterations(&img, 0, &alg, 2));
-    println!("Part 2: {}", num_lit_after_m_iterations(&img, 0, &alg, 50));
+        if aoc2021::has_flag("sparse") {
+            let enhanced = sparse::enhance_m_times(&img, 0, &alg, m);
+            // The background is dark here, so the count is finite
+            println!("{}: {}", label, enhanced.num_lit().unwrap());
+            if let Some(((min_r, min_c), (max_r, max_c))) = enhanced.bounds() {
+                println!(
+                    "  lit pixels lie in rows {}..={}, cols {}..={}",
+                    min_r, max_r, min_c, max_c
+                );
+            }
+        } else if aoc2021::has_flag("bits") {
+            println!(
+                "{}: {}",
+                label,
+                bits::num_lit_after_m_iterations(&img, 0, &alg, m)
+            );
+        } else {
+            println!(
+                "{}: {}",
+                label,
+                num_lit_after_m_iterations(&img, 0, &alg, m)
+            );
+        }
+    }
 
     Ok(())
 }
@@ -107,7 +311,7 @@ mod tests {
 
     #[test]
     fn test_input_parse() {
-        let (alg, mat) = parse_input(SAMPLE_INPUT);
+        let (alg, mat) = parse_input(SAMPLE_INPUT).unwrap();
 
         assert_eq!(alg[0], 0);
         assert_eq!(alg[30], 1);
@@ -131,17 +335,80 @@ mod tests {
     }
 
     #[test]
-    fn test_enhance() {
-        let (alg, mat) = parse_input(SAMPLE_INPUT);
+    fn test_parse_errors() {
+        let alg = SAMPLE_INPUT.lines().next().unwrap();
+
+        assert_eq!(Err(ParseError::MissingAlgorithm), parse_input(""));
+        assert_eq!(
+            Err(ParseError::WrongAlgorithmLength(511)),
+            parse_input(&format!("{}\n\n#.\n", &alg[1..]))
+        );
+        assert_eq!(
+            Err(ParseError::MissingBlankLine),
+            parse_input(&format!("{}\n#.\n", alg))
+        );
+        assert_eq!(Err(ParseError::MissingBlankLine), parse_input(alg));
+        assert_eq!(
+            Err(ParseError::InvalidChar {
+                line: 4,
+                column: 2,
+                found: 'o'
+            }),
+            parse_input(&format!("{}\n\n#.\n.o\n", alg))
+        );
+        assert_eq!(
+            Err(ParseError::InvalidChar {
+                line: 1,
+                column: 3,
+                found: '0'
+            }),
+            parse_input(&format!("..0{}\n\n#.\n", &alg[3..]))
+        );
+        assert_eq!(
+            Err(ParseError::UnevenRows { line: 4 }),
+            parse_input(&format!("{}\n\n#.\n#\n", alg))
+        );
+    }
 
-        let src_n = mat.len();
-        let src_m = mat[0].len();
-        let new_capacity = (src_n + 2) * (src_m + 2);
-        let src_mat = mat.into_iter().flatten().collect::<Vec<_>>();
-        let mut dst_mat: Vec<u8> = Vec::with_capacity(new_capacity);
-        dst_mat.resize(new_capacity, 0);
+    #[test]
+    fn test_background() {
+        let (mut alg, _) = parse_input(SAMPLE_INPUT).unwrap();
+
+        assert_eq!(Background::StaysDark, Background::classify(&alg));
+        assert!(!Background::StaysDark.is_lit_after(3));
+
+        alg[0] = 1;
+        alg[511] = 0;
+        let background = Background::classify(&alg);
+        assert_eq!(Background::Blinks, background);
+        assert!(!background.is_lit_after(0));
+        assert!(background.is_lit_after(1));
+        assert!(!background.is_lit_after(50));
+        assert!(background.is_lit_after(51));
+
+        alg[511] = 1;
+        let background = Background::classify(&alg);
+        assert_eq!(Background::StaysLit, background);
+        assert!(!background.is_lit_after(0));
+        assert!(background.is_lit_after(1));
+        assert!(background.is_lit_after(50));
+    }
+
+    #[test]
+    fn test_to_str() {
+        let (_, mat) = parse_input(SAMPLE_INPUT).unwrap();
+        assert_eq!(
+            SAMPLE_INPUT.split("\n\n").nth(1).unwrap().trim(),
+            to_str(&mat)
+        );
+    }
+
+    #[test]
+    fn test_enhance() {
+        let (alg, mat) = parse_input(SAMPLE_INPUT).unwrap();
 
-        let fill = enhance(&src_mat, (src_n, src_m), 0, &mut dst_mat, &alg);
+        let mut automaton = Automaton::new(&mat, Boundary::Infinite(0)).unwrap();
+        enhance(&mut automaton, &alg);
 
         let expected = vec![
             0, 1, 1, 0, 1, 1, 0, // Leave this comment
@@ -153,15 +420,15 @@ mod tests {
             0, 0, 0, 1, 0, 1, 0,
         ];
 
-        assert_eq!(expected.len(), new_capacity);
-        assert_eq!(fill, 0);
+        assert_eq!((7, 7), (automaton.rows(), automaton.cols()));
+        assert_eq!(Some(0), automaton.background());
 
-        assert_eq!(expected, dst_mat);
+        assert_eq!(expected, automaton.cells());
     }
 
     #[test]
     fn test_solution() {
-        let (alg, mat) = parse_input(SAMPLE_INPUT);
+        let (alg, mat) = parse_input(SAMPLE_INPUT).unwrap();
 
         assert_eq!(35, num_lit_after_m_iterations(&mat, 0, &alg, 2));
     }
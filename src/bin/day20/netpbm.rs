@@ -0,0 +1,255 @@
+use std::error::Error;
+
+// Reading and writing images in the netpbm formats. Lit pixels are stored as
+// black (1) in bitmaps, like `#` on the puzzle's paper. Greymaps are read the
+// same way round, treating anything darker than half of the maximum as lit.
+
+struct Header {
+    magic: u8,
+    width: usize,
+    height: usize,
+    maxval: usize,
+}
+
+// Splits off whitespace-separated header tokens, skipping `#` comments
+struct Tokens<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn skip_space(&mut self) {
+        while let Some(&b) = self.bytes.get(self.pos) {
+            if b == b'#' {
+                while self.bytes.get(self.pos).is_some_and(|&b| b != b'\n') {
+                    self.pos += 1;
+                }
+            } else if b.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Option<&'a [u8]> {
+        self.skip_space();
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| !b.is_ascii_whitespace() && *b != b'#')
+        {
+            self.pos += 1;
+        }
+        (self.pos > start).then(|| &self.bytes[start..self.pos])
+    }
+
+    fn next_number(&mut self, what: &str) -> Result<usize, Box<dyn Error>> {
+        let token = self
+            .next_token()
+            .ok_or(format!("Missing {} in netpbm data", what))?;
+        std::str::from_utf8(token)?
+            .parse::<usize>()
+            .map_err(|e| format!("Invalid {} in netpbm data: {}", what, e).into())
+    }
+}
+
+fn read_header(tokens: &mut Tokens) -> Result<Header, Box<dyn Error>> {
+    let magic = match tokens.next_token() {
+        Some([b'P', m @ (b'1' | b'2' | b'4' | b'5')]) => *m,
+        _ => return Err("Not a P1, P2, P4 or P5 netpbm image".into()),
+    };
+    let width = tokens.next_number("width")?;
+    let height = tokens.next_number("height")?;
+    let maxval = match magic {
+        b'2' | b'5' => tokens.next_number("maximum value")?,
+        _ => 1,
+    };
+    if maxval == 0 || maxval > 65535 {
+        return Err(format!("Invalid maximum value {} in netpbm data", maxval).into());
+    }
+
+    Ok(Header {
+        magic,
+        width,
+        height,
+        maxval,
+    })
+}
+
+pub fn read(bytes: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    let mut tokens = Tokens { bytes, pos: 0 };
+    let header = read_header(&mut tokens)?;
+    let (width, height) = (header.width, header.height);
+    let is_lit = |v: usize| -> u8 { (v * 2 < header.maxval) as u8 };
+
+    let pixels: Vec<u8> = match header.magic {
+        b'1' => {
+            // Plain bitmaps don't need whitespace between pixels, but may
+            // still have comments among them
+            let mut pixels = Vec::with_capacity(width * height);
+            while pixels.len() < width * height {
+                tokens.skip_space();
+                match bytes.get(tokens.pos) {
+                    Some(&b @ (b'0' | b'1')) => pixels.push(b - b'0'),
+                    Some(&b) => return Err(format!("Invalid bitmap pixel {:?}", b as char).into()),
+                    None => break,
+                }
+                tokens.pos += 1;
+            }
+            pixels
+        }
+        b'2' => (0..width * height)
+            .map(|_| tokens.next_number("pixel").map(is_lit))
+            .collect::<Result<_, _>>()?,
+        _ => {
+            // A single whitespace byte separates the header from raw data
+            let data = bytes.get(tokens.pos + 1..).unwrap_or(&[]);
+            if header.magic == b'4' {
+                let row_bytes = width.div_ceil(8);
+                if data.len() < row_bytes * height {
+                    return Err("Truncated raw bitmap data".into());
+                }
+                (0..height)
+                    .flat_map(|r| {
+                        let row = &data[r * row_bytes..(r + 1) * row_bytes];
+                        (0..width).map(move |c| (row[c / 8] >> (7 - c % 8)) & 1)
+                    })
+                    .collect()
+            } else {
+                let sample_bytes = if header.maxval < 256 { 1 } else { 2 };
+                if data.len() < sample_bytes * width * height {
+                    return Err("Truncated raw greymap data".into());
+                }
+                data.chunks(sample_bytes)
+                    .take(width * height)
+                    .map(|s| is_lit(s.iter().fold(0, |v, b| (v << 8) | *b as usize)))
+                    .collect()
+            }
+        }
+    };
+
+    if pixels.len() != width * height {
+        return Err(format!(
+            "Expected {} pixels in netpbm data, found {}",
+            width * height,
+            pixels.len()
+        )
+        .into());
+    }
+
+    Ok(pixels
+        .chunks(width.max(1))
+        .take(height)
+        .map(|row| row.to_vec())
+        .collect())
+}
+
+// The enhancement algorithm may be stored as any image with 512 pixels; they
+// are read row by row
+pub fn read_alg(bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let alg = read(bytes)?.into_iter().flatten().collect::<Vec<_>>();
+    if alg.len() != 512 {
+        return Err(format!("Algorithm image has {} pixels, expected 512", alg.len()).into());
+    }
+    Ok(alg)
+}
+
+// Plain (P1) bitmap, one image row per line
+pub fn write_plain(img: &[Vec<u8>]) -> Vec<u8> {
+    let width = img.first().map_or(0, |row| row.len());
+    let mut out = format!("P1\n{} {}\n", width, img.len()).into_bytes();
+    for row in img {
+        let line = row
+            .iter()
+            .map(|px| if *px == 1 { "1" } else { "0" })
+            .collect::<Vec<_>>()
+            .join(" ");
+        out.extend_from_slice(line.as_bytes());
+        out.push(b'\n');
+    }
+    out
+}
+
+// Raw (P4) bitmap, eight pixels to a byte
+pub fn write_raw(img: &[Vec<u8>]) -> Vec<u8> {
+    let width = img.first().map_or(0, |row| row.len());
+    let mut out = format!("P4\n{} {}\n", width, img.len()).into_bytes();
+    for row in img {
+        for byte_pixels in row.chunks(8) {
+            let byte = byte_pixels
+                .iter()
+                .enumerate()
+                .fold(0u8, |b, (i, px)| b | (px << (7 - i)));
+            out.push(byte);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_input;
+
+    const SAMPLE_INPUT: &str = include_str!("./sample.txt");
+
+    #[test]
+    fn test_round_trip() {
+        let (alg, mat) = parse_input(SAMPLE_INPUT).unwrap();
+
+        assert_eq!(mat, read(&write_plain(&mat)).unwrap());
+        assert_eq!(mat, read(&write_raw(&mat)).unwrap());
+
+        // Wider than a byte, and not a multiple of 8
+        let wide = vec![alg[..11].to_vec(), alg[11..22].to_vec()];
+        assert_eq!(wide, read(&write_raw(&wide)).unwrap());
+
+        assert_eq!(
+            alg,
+            read_alg(&write_raw(std::slice::from_ref(&alg))).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read() {
+        let plain = b"P1\n# a comment\n3 2\n100\n0 1\n1";
+        assert_eq!(vec![vec![1, 0, 0], vec![0, 1, 1]], read(plain).unwrap());
+
+        let commented = b"P1 3 2\n100 # first row\n# and the second\n011\n";
+        assert_eq!(vec![vec![1, 0, 0], vec![0, 1, 1]], read(commented).unwrap());
+
+        let raw = b"P4 3 2\n\xa0\x60";
+        assert_eq!(vec![vec![1, 0, 1], vec![0, 1, 1]], read(raw).unwrap());
+
+        let grey = b"P2 2 2 255\n0 200\n128 127\n";
+        assert_eq!(vec![vec![1, 0], vec![0, 1]], read(grey).unwrap());
+
+        let raw_grey = b"P5 2 1 65535\n\xff\xff\x00\x10";
+        assert_eq!(vec![vec![0, 1]], read(raw_grey).unwrap());
+
+        assert!(read(b"P3 1 1 255 0 0 0").is_err());
+        assert!(read(b"P1 2 2 1 0 1").is_err());
+        assert!(read(b"P4 9 1\n\xff").is_err());
+        assert!(read_alg(b"P1 2 1 1 0").is_err());
+    }
+
+    #[test]
+    fn test_formats_agree() {
+        let (_, mat) = parse_input(SAMPLE_INPUT).unwrap();
+
+        // A black pixel is lit in every format
+        let mut grey = format!("P5\n{} {}\n255\n", mat[0].len(), mat.len()).into_bytes();
+        grey.extend(
+            mat.iter()
+                .flatten()
+                .map(|&px| if px == 1 { 0 } else { 255 }),
+        );
+
+        let from_plain = read(&write_plain(&mat)).unwrap();
+        assert_eq!(mat, from_plain);
+        assert_eq!(from_plain, read(&write_raw(&mat)).unwrap());
+        assert_eq!(from_plain, read(&grey).unwrap());
+    }
+}
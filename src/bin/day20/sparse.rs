@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+
+// An unbounded image stored as the pixels that differ from the (infinite)
+// background. Memory scales with the number of such pixels rather than with
+// the bounding box, and coordinates may go negative as the image grows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseImage {
+    exceptions: HashSet<(i64, i64)>,
+    background: u8,
+}
+
+impl SparseImage {
+    // Row 0, column 0 is the top-left pixel of `img`
+    pub fn from_rows(img: &[Vec<u8>], background: u8) -> SparseImage {
+        let exceptions = img
+            .iter()
+            .enumerate()
+            .flat_map(|(r, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(move |(_, px)| **px != background)
+                    .map(move |(c, _)| (r as i64, c as i64))
+            })
+            .collect();
+
+        SparseImage {
+            exceptions,
+            background,
+        }
+    }
+
+    // 1 if the pixel at (r, c) is lit, 0 if not; any coordinate is fine
+    pub fn pixel(&self, r: i64, c: i64) -> u8 {
+        if self.exceptions.contains(&(r, c)) {
+            1 - self.background
+        } else {
+            self.background
+        }
+    }
+
+    // None when the background is lit, since then infinitely many pixels are
+    pub fn num_lit(&self) -> Option<usize> {
+        (self.background == 0).then_some(self.exceptions.len())
+    }
+
+    // Inclusive ((min row, min col), (max row, max col)) of the pixels that
+    // differ from the background, or None if there aren't any
+    pub fn bounds(&self) -> Option<((i64, i64), (i64, i64))> {
+        let min_r = self.exceptions.iter().map(|p| p.0).min()?;
+        let max_r = self.exceptions.iter().map(|p| p.0).max()?;
+        let min_c = self.exceptions.iter().map(|p| p.1).min()?;
+        let max_c = self.exceptions.iter().map(|p| p.1).max()?;
+        Some(((min_r, min_c), (max_r, max_c)))
+    }
+
+    pub fn enhance(&self, alg: &[u8]) -> SparseImage {
+        let background = if self.background == 1 {
+            alg[511]
+        } else {
+            alg[0]
+        };
+
+        // A pixel whose 3x3 window is all background becomes the new
+        // background, so only the neighbourhoods of exceptions need a look
+        let mut candidates: HashSet<(i64, i64)> = HashSet::new();
+        for &(r, c) in self.exceptions.iter() {
+            for dr in -1..=1 {
+                for dc in -1..=1 {
+                    candidates.insert((r + dr, c + dc));
+                }
+            }
+        }
+
+        let exceptions = candidates
+            .into_iter()
+            .filter(|&(r, c)| {
+                let mut idx = 0usize;
+                for wr in (r - 1)..=(r + 1) {
+                    for wc in (c - 1)..=(c + 1) {
+                        idx = (idx << 1) | (self.pixel(wr, wc) as usize);
+                    }
+                }
+                alg[idx] != background
+            })
+            .collect();
+
+        SparseImage {
+            exceptions,
+            background,
+        }
+    }
+}
+
+pub fn enhance_m_times(img: &[Vec<u8>], fill: u8, alg: &[u8], m: usize) -> SparseImage {
+    let mut img = SparseImage::from_rows(img, fill);
+    for _ in 0..m {
+        img = img.enhance(alg);
+    }
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_input;
+    use std::time::Instant;
+
+    const SAMPLE_INPUT: &str = include_str!("./sample.txt");
+
+    #[test]
+    fn test_enhance() {
+        let (alg, mat) = parse_input(SAMPLE_INPUT).unwrap();
+        let img = SparseImage::from_rows(&mat, 0).enhance(&alg);
+
+        assert_eq!(Some(((-1, -1), (5, 5))), img.bounds());
+        assert_eq!(1, img.pixel(-1, 0));
+        assert_eq!(0, img.pixel(-1, -1));
+        assert_eq!(1, img.pixel(5, 4));
+        assert_eq!(0, img.pixel(1_000_000, -1_000_000));
+        assert_eq!(Some(24), img.num_lit());
+    }
+
+    #[test]
+    fn test_agrees_with_dense() {
+        let (alg, mat) = parse_input(SAMPLE_INPUT).unwrap();
+        for m in [1, 2, 5, 10] {
+            assert_eq!(
+                Some(crate::num_lit_after_m_iterations(&mat, 0, &alg, m)),
+                enhance_m_times(&mat, 0, &alg, m).num_lit()
+            );
+        }
+
+        let blinking = crate::blinking(&alg);
+        for m in [2, 4] {
+            assert_eq!(
+                Some(crate::num_lit_after_m_iterations(&mat, 0, &blinking, m)),
+                enhance_m_times(&mat, 0, &blinking, m).num_lit()
+            );
+        }
+        assert_eq!(None, enhance_m_times(&mat, 0, &blinking, 3).num_lit());
+    }
+
+    // The dense buffer wins when most of the bounding box ends up lit, as it
+    // does for the puzzle input; the sparse set wins when only a few pixels in
+    // a large image are lit.
+    #[test]
+    #[ignore]
+    fn bench_sparse_vs_dense() {
+        let (alg, mat) = parse_input(include_str!("./input")).unwrap();
+
+        // A 1000x1000 image with a handful of lit pixels, under a rule that
+        // only keeps a pixel lit while it has no lit neighbours
+        let mut isolated = vec![0u8; 512];
+        isolated[0b000_010_000] = 1;
+        let mut scattered = vec![vec![0u8; 1000]; 1000];
+        for i in 0..50 {
+            scattered[(i * 389) % 1000][(i * 613) % 1000] = 1;
+        }
+
+        for (name, alg, img, m) in [
+            ("puzzle input", &alg, &mat, 50),
+            ("scattered pixels", &isolated, &scattered, 10),
+        ] {
+            let start = Instant::now();
+            let dense = crate::num_lit_after_m_iterations(img, 0, alg, m);
+            let dense_elapsed = start.elapsed();
+
+            let start = Instant::now();
+            let sparse = enhance_m_times(img, 0, alg, m).num_lit();
+            let sparse_elapsed = start.elapsed();
+
+            assert_eq!(Some(dense), sparse);
+            println!(
+                "{}, {} iterations: dense {:?}, sparse {:?}",
+                name, m, dense_elapsed, sparse_elapsed
+            );
+        }
+    }
+}
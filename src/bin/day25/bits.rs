@@ -0,0 +1,335 @@
+use super::StepStats;
+use aoc2021::cycle::{self, Outcome};
+use aoc2021::packed::{mask_tail, words_for};
+
+// The sea floor as one bitset per herd per row, column c of a row being bit
+// c % 64 of word c / 64. Bits past `width` are always 0. The eastbound herd
+// moves by rotating each row one column along, and the southbound herd by
+// masking each row against the free cells of the row below.
+#[derive(Clone, Debug, PartialEq, Hash)]
+pub struct Bitboard {
+    width: usize,
+    east: Vec<Vec<u64>>,
+    south: Vec<Vec<u64>>,
+    // Cells holding anything other than a cucumber or '.', which never move
+    // and are never free
+    blocked: Vec<Vec<u64>>,
+}
+
+fn bit(row: &[u64], c: usize) -> bool {
+    (row[c >> 6] >> (c & 63)) & 1 == 1
+}
+
+// Every bit moves one column east, the last column wrapping round to the first
+fn rotate_east(row: &[u64], width: usize) -> Vec<u64> {
+    let mut out = Vec::with_capacity(row.len());
+    let mut carry = bit(row, width - 1) as u64;
+    for &word in row {
+        out.push((word << 1) | carry);
+        carry = word >> 63;
+    }
+    mask_tail(&mut out, width);
+    out
+}
+
+// Every bit moves one column west, the first column wrapping round to the last
+fn rotate_west(row: &[u64], width: usize) -> Vec<u64> {
+    let mut out = vec![0; row.len()];
+    for i in 0..row.len() {
+        let next = row.get(i + 1).copied().unwrap_or(0);
+        out[i] = (row[i] >> 1) | (next << 63);
+    }
+    mask_tail(&mut out, width);
+    if row[0] & 1 == 1 {
+        out[(width - 1) >> 6] |= 1 << ((width - 1) & 63);
+    }
+    out
+}
+
+impl Bitboard {
+    pub fn from_rows(grid: &[Vec<char>]) -> Result<Bitboard, String> {
+        let width = grid.first().map_or(0, |row| row.len());
+        if grid.is_empty() || width == 0 {
+            return Err("The sea floor can't be empty".into());
+        }
+
+        let mut east = vec![vec![0u64; words_for(width)]; grid.len()];
+        let mut south = east.clone();
+        let mut blocked = east.clone();
+        for (r, row) in grid.iter().enumerate() {
+            if row.len() != width {
+                return Err("Grid rows have different lengths".into());
+            }
+            for (c, cell) in row.iter().enumerate() {
+                match cell {
+                    '>' => east[r][c >> 6] |= 1 << (c & 63),
+                    'v' => south[r][c >> 6] |= 1 << (c & 63),
+                    '.' => (),
+                    _ => blocked[r][c >> 6] |= 1 << (c & 63),
+                }
+            }
+        }
+
+        Ok(Bitboard {
+            width,
+            east,
+            south,
+            blocked,
+        })
+    }
+
+    fn free(&self, r: usize) -> Vec<u64> {
+        let mut free = self.east[r]
+            .iter()
+            .zip(self.south[r].iter())
+            .zip(self.blocked[r].iter())
+            .map(|((e, s), b)| !(e | s | b))
+            .collect::<Vec<_>>();
+        mask_tail(&mut free, self.width);
+        free
+    }
+
+    fn move_east(&mut self) -> usize {
+        let mut moves = 0;
+        for r in 0..self.east.len() {
+            // A cucumber can move if the cell east of it is free, i.e. if its
+            // bit is set in the free cells shifted one column west
+            let ahead_free = rotate_west(&self.free(r), self.width);
+            let moving = self.east[r]
+                .iter()
+                .zip(ahead_free.iter())
+                .map(|(e, f)| e & f)
+                .collect::<Vec<_>>();
+            moves += moving
+                .iter()
+                .map(|w| w.count_ones() as usize)
+                .sum::<usize>();
+
+            let arrived = rotate_east(&moving, self.width);
+            for ((e, m), a) in self.east[r].iter_mut().zip(moving).zip(arrived) {
+                *e = (*e & !m) | a;
+            }
+        }
+        moves
+    }
+
+    fn move_south(&mut self) -> usize {
+        let rows = self.south.len();
+        let free = (0..rows).map(|r| self.free(r)).collect::<Vec<_>>();
+
+        // Every row's movers are worked out before any of them move
+        let moving = (0..rows)
+            .map(|r| {
+                self.south[r]
+                    .iter()
+                    .zip(free[(r + 1) % rows].iter())
+                    .map(|(s, f)| s & f)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut moves = 0;
+        for r in 0..rows {
+            let arrived = &moving[(r + rows - 1) % rows];
+            for ((s, m), a) in self.south[r].iter_mut().zip(&moving[r]).zip(arrived) {
+                *s = (*s & !m) | a;
+            }
+            moves += moving[r]
+                .iter()
+                .map(|w| w.count_ones() as usize)
+                .sum::<usize>();
+        }
+        moves
+    }
+
+    // Same as `run_iteration` in main.rs under the default rules
+    pub fn step(&mut self) -> StepStats {
+        StepStats {
+            moves: vec![self.move_east(), self.move_south()],
+        }
+    }
+
+    // Same as `simulate` in main.rs
+    pub fn run(&mut self, max_steps: Option<usize>) -> (Outcome, Vec<StepStats>) {
+        let mut stats = Vec::new();
+        let outcome = cycle::run(self, max_steps, |b| stats.push(b.step()));
+        if let Some(n) = outcome.steps() {
+            stats.truncate(n);
+        }
+        (outcome, stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_input, run_iteration, simulate, to_str, HerdRules};
+    use aoc2021::automaton::{Automaton, Boundary};
+    use aoc2021::testing::Rng;
+    use std::time::Instant;
+
+    const SAMPLE_INPUT: &str = include_str!("./sample.txt");
+
+    fn to_rows(board: &Bitboard) -> Vec<Vec<char>> {
+        board
+            .east
+            .iter()
+            .zip(board.south.iter())
+            .zip(board.blocked.iter())
+            .map(|((east, south), blocked)| {
+                (0..board.width)
+                    .map(|c| match (bit(east, c), bit(south, c), bit(blocked, c)) {
+                        (true, _, _) => '>',
+                        (_, true, _) => 'v',
+                        (_, _, true) => '#',
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn random_grid(rng: &mut Rng, rows: usize, cols: usize) -> Vec<Vec<char>> {
+        (0..rows)
+            .map(|_| {
+                (0..cols)
+                    .map(|_| match rng.next_u64() % 3 {
+                        0 => '>',
+                        1 => 'v',
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rotate() {
+        for width in [1, 5, 64, 65, 130] {
+            let mut row = vec![0u64; words_for(width)];
+            row[0] = 1;
+            let mut c = 0;
+            for _ in 0..width + 3 {
+                row = rotate_east(&row, width);
+                c = (c + 1) % width;
+                assert!(bit(&row, c));
+                assert_eq!(1, row.iter().map(|w| w.count_ones()).sum::<u32>());
+            }
+            for _ in 0..width + 3 {
+                row = rotate_west(&row, width);
+                c = (c + width - 1) % width;
+                assert!(bit(&row, c));
+                assert_eq!(1, row.iter().map(|w| w.count_ones()).sum::<u32>());
+            }
+        }
+    }
+
+    #[test]
+    fn test_solution() {
+        let grid = parse_input(SAMPLE_INPUT);
+        let mut board = Bitboard::from_rows(&grid).unwrap();
+        assert_eq!(Outcome::FixedPoint { step: 58 }, board.run(None).0);
+
+        let mut board = Bitboard::from_rows(&parse_input(include_str!("./input"))).unwrap();
+        assert_eq!(Outcome::FixedPoint { step: 417 }, board.run(None).0);
+
+        assert!(Bitboard::from_rows(&[]).is_err());
+        assert!(Bitboard::from_rows(&[vec!['>'], vec!['>', '.']]).is_err());
+    }
+
+    #[test]
+    fn test_agrees_with_automaton() {
+        let mut rng = Rng(0xc0c0a);
+        for (rows, cols) in [(1, 1), (3, 7), (10, 64), (9, 65), (20, 150)] {
+            let grid = random_grid(&mut rng, rows, cols);
+            let mut board = Bitboard::from_rows(&grid).unwrap();
+            let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+
+            for _ in 0..30 {
+                assert_eq!(
+                    run_iteration(&mut automaton, &HerdRules::default()),
+                    board.step()
+                );
+                assert_eq!(automaton.to_rows(), to_rows(&board));
+            }
+
+            let mut board = Bitboard::from_rows(&grid).unwrap();
+            let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+            assert_eq!(
+                simulate(&mut automaton, &HerdRules::default(), None),
+                board.run(None)
+            );
+        }
+
+        // The lone cucumbers from main.rs that go round forever
+        let grid = parse_input(">..\n...\n.v.");
+        let mut board = Bitboard::from_rows(&grid).unwrap();
+        assert_eq!(
+            Outcome::Cycle {
+                start: 1,
+                period: 3
+            },
+            board.run(None).0
+        );
+    }
+
+    #[test]
+    fn test_obstacles() {
+        // The '#' is in the way, so the cucumber only gets one step
+        let grid = parse_input(">.#.\n....");
+        let mut board = Bitboard::from_rows(&grid).unwrap();
+        let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+        let (outcome, stats) = board.run(None);
+        assert_eq!(Outcome::FixedPoint { step: 2 }, outcome);
+        assert_eq!(
+            simulate(&mut automaton, &HerdRules::default(), None),
+            (outcome, stats)
+        );
+        assert_eq!(".>#.\n....", to_str(&to_rows(&board)));
+
+        let mut rng = Rng(0x0b57);
+        let mut grid = random_grid(&mut rng, 12, 70);
+        for _ in 0..40 {
+            let (r, c) = (rng.next_u64() as usize % 12, rng.next_u64() as usize % 70);
+            grid[r][c] = '#';
+        }
+        let mut board = Bitboard::from_rows(&grid).unwrap();
+        let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+        for _ in 0..30 {
+            assert_eq!(
+                run_iteration(&mut automaton, &HerdRules::default()),
+                board.step()
+            );
+            assert_eq!(automaton.to_rows(), to_rows(&board));
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_bits_vs_automaton() {
+        let mut rng = Rng(0xbe4c4);
+        for (rows, cols, steps) in [(137, 139, 417), (1000, 1000, 100), (2000, 3000, 20)] {
+            let grid = random_grid(&mut rng, rows, cols);
+
+            let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+            let start = Instant::now();
+            for _ in 0..steps {
+                run_iteration(&mut automaton, &HerdRules::default());
+            }
+            let automaton_elapsed = start.elapsed();
+
+            let mut board = Bitboard::from_rows(&grid).unwrap();
+            let start = Instant::now();
+            for _ in 0..steps {
+                board.step();
+            }
+            let bits_elapsed = start.elapsed();
+
+            assert_eq!(automaton.to_rows(), to_rows(&board));
+            println!(
+                "{}x{}, {} steps: automaton {:?}, bitboard {:?}",
+                rows, cols, steps, automaton_elapsed, bits_elapsed
+            );
+        }
+    }
+}
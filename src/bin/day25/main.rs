@@ -1,4 +1,10 @@
+use aoc2021::automaton::{Automaton, Boundary, Neighbourhood};
+use aoc2021::cycle::Outcome;
+use aoc2021::visualize::{self, Control, Visualizer};
 use std::error::Error;
+use std::str::FromStr;
+
+mod bits;
 
 fn parse_input(s: &str) -> Vec<Vec<char>> {
     s.lines()
@@ -6,71 +12,592 @@ fn parse_input(s: &str) -> Vec<Vec<char>> {
         .collect::<Vec<Vec<_>>>()
 }
 
-#[allow(dead_code)]
-fn to_str(grid: &Vec<Vec<char>>) -> String {
+fn to_str(grid: &[Vec<char>]) -> String {
     grid.iter()
         .map(|row| row.iter().collect::<String>())
         .collect::<Vec<_>>()
         .join("\n")
 }
 
-fn run_iteration(src: &Vec<Vec<char>>, dst: &mut Vec<Vec<char>>, tmp: &mut Vec<Vec<char>>) {
-    let num_rows = src.len();
-    let row_len = src[0].len();
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    // (row, column) offset of the cell a cucumber moves into
+    fn delta(self) -> (isize, isize) {
+        match self {
+            Direction::North => (-1, 0),
+            Direction::East => (0, 1),
+            Direction::South => (1, 0),
+            Direction::West => (0, -1),
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "north" | "n" => Ok(Direction::North),
+            "east" | "e" => Ok(Direction::East),
+            "south" | "s" => Ok(Direction::South),
+            "west" | "w" => Ok(Direction::West),
+            _ => Err(format!("Unknown direction {:?}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Herd {
+    symbol: char,
+    direction: Direction,
+    // Herds move in increasing order of phase; herds that share a phase
+    // move at the same time
+    phase: usize,
+}
+
+// Which symbols move where, and when. Any symbol that is neither `empty` nor
+// a herd stays put and blocks the way.
+#[derive(Clone, Debug, PartialEq)]
+struct HerdRules {
+    empty: char,
+    herds: Vec<Herd>,
+    // Indices into `herds` of the herds that move in each phase, in order
+    phases: Vec<Vec<usize>>,
+}
+
+impl Default for HerdRules {
+    fn default() -> Self {
+        HerdRules::with_herds(
+            '.',
+            vec![
+                Herd {
+                    symbol: '>',
+                    direction: Direction::East,
+                    phase: 0,
+                },
+                Herd {
+                    symbol: 'v',
+                    direction: Direction::South,
+                    phase: 1,
+                },
+            ],
+        )
+    }
+}
+
+impl HerdRules {
+    fn with_herds(empty: char, herds: Vec<Herd>) -> Self {
+        let mut phase_numbers = herds.iter().map(|h| h.phase).collect::<Vec<_>>();
+        phase_numbers.sort_unstable();
+        phase_numbers.dedup();
 
-    for (i, row) in src.iter().enumerate() {
-        (&mut tmp[i]).copy_from_slice(row.as_slice());
-        (&mut dst[i]).copy_from_slice(row.as_slice());
+        let phases = phase_numbers
+            .into_iter()
+            .map(|p| (0..herds.len()).filter(|&i| herds[i].phase == p).collect())
+            .collect();
+
+        HerdRules {
+            empty,
+            herds,
+            phases,
+        }
     }
 
-    // eastbound
-    for (i, row) in src.iter().enumerate() {
-        for (j, cucumber) in row.iter().enumerate() {
-            if *cucumber == '>' && src[i][(j + 1) % row_len] == '.' {
-                dst[i][j] = '.';
-                dst[i][(j + 1) % row_len] = '>';
-                tmp[i][j] = '.';
-                tmp[i][(j + 1) % row_len] = '>';
+    // Herds are given as comma-separated `SYMBOL:DIRECTION[:PHASE]`, e.g.
+    // `>:east,v:south` for the puzzle's rules. Herds without a phase each get
+    // a phase of their own, numbered in the order listed after the largest
+    // phase that is given.
+    fn new(spec: &str, empty: char) -> Result<Self, String> {
+        let mut herds: Vec<Herd> = Vec::new();
+        let mut given_phases = Vec::new();
+        for entry in spec.split(',') {
+            let fields = entry.split(':').collect::<Vec<_>>();
+            let (symbol, direction, phase) = match fields[..] {
+                [symbol, direction] => (symbol, direction, None),
+                [symbol, direction, phase] => (symbol, direction, Some(phase)),
+                _ => {
+                    return Err(format!(
+                        "Expected SYMBOL:DIRECTION[:PHASE], found {:?}",
+                        entry
+                    ))
+                }
+            };
+
+            let mut chars = symbol.chars();
+            let symbol = match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(format!("Herd symbol {:?} isn't a single character", symbol)),
+            };
+            if symbol == empty {
+                return Err(format!("Herd symbol {:?} is also the empty symbol", symbol));
+            }
+            if herds.iter().any(|h| h.symbol == symbol) {
+                return Err(format!("Herd symbol {:?} is given twice", symbol));
             }
+
+            let phase =
+                match phase {
+                    Some(p) => Some(p.parse::<usize>().map_err(|e| {
+                        format!("Invalid phase {:?} for herd {:?}: {}", p, symbol, e)
+                    })?),
+                    None => None,
+                };
+
+            herds.push(Herd {
+                symbol,
+                direction: direction.parse()?,
+                phase: 0,
+            });
+            given_phases.push(phase);
+        }
+
+        let mut next_phase = match given_phases.iter().flatten().max() {
+            Some(p) => p.checked_add(1),
+            None => Some(0),
+        };
+        for (herd, phase) in herds.iter_mut().zip(given_phases) {
+            herd.phase = match phase {
+                Some(p) => p,
+                None => {
+                    let p = next_phase.ok_or("No phase left for the herds without one")?;
+                    next_phase = p.checked_add(1);
+                    p
+                }
+            };
+        }
+
+        Ok(HerdRules::with_herds(empty, herds))
+    }
+
+    fn from_flags() -> Result<Self, Box<dyn Error>> {
+        let default = HerdRules::default();
+        let empty = aoc2021::parse_flag_value("empty")?.unwrap_or(default.empty);
+
+        match aoc2021::get_flag_value("herds") {
+            Some(spec) => Ok(HerdRules::new(&spec, empty).map_err(|e| format!("--herds: {}", e))?),
+            None if empty == default.empty => Ok(default),
+            None => Err("--empty needs --herds to go with it".into()),
         }
     }
+}
+
+// One phase's worth of moves. A cucumber moves if the cell ahead of it is
+// empty, unless a cucumber of a herd listed before it in the same phase is
+// moving into that cell too.
+fn move_phase(n: &Neighbourhood<char>, movers: &[&Herd], empty: char) -> char {
+    let center = n.center();
+    // Whether a cucumber of `herd` sits where it would move to `(dr, dc)` from
+    let arrives = |herd: &Herd, dr: isize, dc: isize| {
+        let (hr, hc) = herd.direction.delta();
+        n.get(dr - hr, dc - hc) == herd.symbol
+    };
+
+    if center == empty {
+        return movers
+            .iter()
+            .find(|h| arrives(h, 0, 0))
+            .map_or(empty, |h| h.symbol);
+    }
 
-    // southbound
-    for (i, row) in tmp.iter().enumerate() {
-        for (j, cucumber) in row.iter().enumerate() {
-            if *cucumber == 'v' && tmp[(i + 1) % num_rows][j] == '.' {
-                dst[i][j] = '.';
-                dst[(i + 1) % num_rows][j] = 'v';
+    match movers.iter().position(|h| h.symbol == center) {
+        Some(i) => {
+            let (dr, dc) = movers[i].direction.delta();
+            let beaten = movers[..i].iter().any(|h| arrives(h, dr, dc));
+            if n.get(dr, dc) == empty && !beaten {
+                empty
+            } else {
+                center
             }
         }
+        None => center,
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let input_ = aoc2021::get_input_string()?;
+// The common case of a phase with one herd, without the bookkeeping for
+// herds contesting a cell
+fn move_herd(n: &Neighbourhood<char>, herd: &Herd, empty: char) -> char {
+    let center = n.center();
+    let (dr, dc) = herd.direction.delta();
+    if center == empty {
+        if n.get(-dr, -dc) == herd.symbol {
+            herd.symbol
+        } else {
+            empty
+        }
+    } else if center == herd.symbol && n.get(dr, dc) == empty {
+        empty
+    } else {
+        center
+    }
+}
+
+// Moves made in a step by each herd, in the order the herds are listed
+#[derive(Clone, Debug, PartialEq)]
+struct StepStats {
+    moves: Vec<usize>,
+}
 
-    let mut initial_grid = parse_input(&input_);
-    let mut other_grid = initial_grid.clone();
-    let mut tmp = initial_grid.clone();
+// Each phase moves on the grid the previous phase left behind
+fn run_iteration(grid: &mut Automaton<char>, rules: &HerdRules) -> StepStats {
+    let mut moves = vec![0; rules.herds.len()];
 
-    for i in 1.. {
-        let (src, dst) = if i % 2 == 0 {
-            (&mut initial_grid, &mut other_grid)
+    for phase in rules.phases.iter() {
+        if let [i] = phase[..] {
+            // Every move changes two cells
+            let herd = &rules.herds[i];
+            moves[i] = grid.step(|n| move_herd(n, herd, rules.empty)) / 2;
         } else {
-            (&mut other_grid, &mut initial_grid)
-        };
+            let movers = phase.iter().map(|&i| &rules.herds[i]).collect::<Vec<_>>();
+            let rule = |n: &Neighbourhood<char>| move_phase(n, &movers, rules.empty);
+
+            // Tell the herds apart by the cells they move into
+            let before = grid.cells().to_vec();
+            grid.step(rule);
+            for (b, a) in before.iter().zip(grid.cells()) {
+                if *b == rules.empty && *a != rules.empty {
+                    if let Some(&i) = phase.iter().find(|&&i| rules.herds[i].symbol == *a) {
+                        moves[i] += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    StepStats { moves }
+}
+
+// Runs until the herds stop or get into a cycle, or for `max_steps` steps,
+// recording the moves made in each step
+fn simulate(
+    grid: &mut Automaton<char>,
+    rules: &HerdRules,
+    max_steps: Option<usize>,
+) -> (Outcome, Vec<StepStats>) {
+    let mut stats = Vec::new();
+    let outcome = grid.run(max_steps, |g| stats.push(run_iteration(g, rules)));
+    if let Some(n) = outcome.steps() {
+        stats.truncate(n);
+    }
+    (outcome, stats)
+}
+
+// The grid after `n` steps. Once the herds stop or start repeating
+// themselves, later steps are worked out without simulating them.
+fn state_at(grid: &[Vec<char>], rules: &HerdRules, n: usize) -> Result<Vec<Vec<char>>, String> {
+    let mut automaton = Automaton::new(grid, Boundary::Toroidal)?;
+    let equivalent_step = match simulate(&mut automaton, rules, Some(n)).0 {
+        Outcome::StepLimit => return Ok(automaton.to_rows()),
+        Outcome::FixedPoint { .. } => return Ok(automaton.to_rows()),
+        Outcome::Cycle { start, period } => start + (n - start) % period,
+    };
+
+    let mut automaton = Automaton::new(grid, Boundary::Toroidal)?;
+    for _ in 0..equivalent_step {
+        run_iteration(&mut automaton, rules);
+    }
+    Ok(automaton.to_rows())
+}
+
+// The last step worth showing: the one before the herds stop, or the last
+// one before the grid repeats itself
+fn last_distinct_step(grid: &[Vec<char>], rules: &HerdRules) -> Result<usize, String> {
+    let mut automaton = Automaton::new(grid, Boundary::Toroidal)?;
+    Ok(match simulate(&mut automaton, rules, None).0 {
+        Outcome::FixedPoint { step } => step - 1,
+        Outcome::Cycle { start, period } => start + period - 1,
+        Outcome::StepLimit => unreachable!("no step limit was given"),
+    })
+}
 
-        run_iteration(src, dst, &mut tmp);
+// Shows each step until the herds stop moving or start repeating themselves,
+// or the user quits. Where that is gets worked out first, so that recording
+// herds that never stop still ends.
+fn animate(
+    grid: &[Vec<char>],
+    rules: &HerdRules,
+    visualizer: &mut Visualizer,
+) -> Result<(), Box<dyn Error>> {
+    let last = last_distinct_step(grid, rules)?;
+    let mut automaton = Automaton::new(grid, Boundary::Toroidal)?;
+    let mut title = "Step 0".to_string();
 
-        if src == dst {
-            println!("{}", i);
+    for step in 0..=last {
+        if step > 0 {
+            let stats = run_iteration(&mut automaton, rules);
+            let moves = rules
+                .herds
+                .iter()
+                .zip(stats.moves)
+                .map(|(h, m)| format!("{} '{}'", m, h.symbol))
+                .collect::<Vec<_>>();
+            title = format!("Step {}: {}", step, moves.join(", "));
+        }
+        if visualizer.show(&title, &to_str(&automaton.to_rows()))? == Control::Quit {
             break;
         }
     }
 
-    // println!("{}", to_str(&initial_grid));
-    // println!("");
-    // println!("{}", to_str(&other_grid));
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    if let Some(path) = aoc2021::get_flag_value("replay") {
+        return visualize::replay(&path);
+    }
+
+    let input_ = aoc2021::get_input_string()?;
+    let initial_grid = parse_input(&input_);
+    let rules = HerdRules::from_flags()?;
+
+    if let Some(mut visualizer) = Visualizer::from_flags()? {
+        animate(&initial_grid, &rules, &mut visualizer)?;
+    }
+
+    if let Some(n) = aoc2021::parse_flag_value::<usize>("state-at")? {
+        println!("{}", to_str(&state_at(&initial_grid, &rules, n)?));
+        return Ok(());
+    }
+
+    let (outcome, stats) = if aoc2021::has_flag("bits") {
+        if rules != HerdRules::default() {
+            return Err("--bits only supports the puzzle's own herds".into());
+        }
+        bits::Bitboard::from_rows(&initial_grid)?.run(None)
+    } else {
+        simulate(
+            &mut Automaton::new(&initial_grid, Boundary::Toroidal)?,
+            &rules,
+            None,
+        )
+    };
+
+    if aoc2021::has_flag("stats") {
+        print!("step");
+        for h in rules.herds.iter() {
+            print!("  {:>5}", h.symbol);
+        }
+        println!();
+        for (i, s) in stats.iter().enumerate() {
+            print!("{:>4}", i + 1);
+            for m in s.moves.iter() {
+                print!("  {:>5}", m);
+            }
+            println!();
+        }
+    }
+
+    match outcome {
+        Outcome::FixedPoint { step } => println!("{}", step),
+        Outcome::Cycle { start, period } => {
+            return Err(format!(
+                "The herds never stop moving: the grid after step {} repeats every {} steps",
+                start, period
+            )
+            .into())
+        }
+        Outcome::StepLimit => unreachable!("no step limit was given"),
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_INPUT: &str = include_str!("./sample.txt");
+
+    #[test]
+    fn test_run_iteration() {
+        let mut grid = Automaton::new(&parse_input(SAMPLE_INPUT), Boundary::Toroidal).unwrap();
+        run_iteration(&mut grid, &HerdRules::default());
+
+        let expected = "....>.>v.>
+v.v>.>v.v.
+>v>>..>v..
+>>v>v>.>.v
+.>v.v...v.
+v>>.>vvv..
+..v...>>..
+vv...>>vv.
+>.v.v..v.v";
+        assert_eq!(expected, to_str(&grid.to_rows()));
+    }
+
+    #[test]
+    fn test_solution() {
+        let rules = HerdRules::default();
+        let mut grid = Automaton::new(&parse_input(SAMPLE_INPUT), Boundary::Toroidal).unwrap();
+        let (outcome, stats) = simulate(&mut grid, &rules, None);
+
+        assert_eq!(Outcome::FixedPoint { step: 58 }, outcome);
+        assert_eq!(58, stats.len());
+        assert!(stats[57].moves.iter().all(|&m| m == 0));
+        assert!(stats[..57].iter().all(|s| s.moves.iter().any(|&m| m > 0)));
+    }
+
+    #[test]
+    fn test_step_stats() {
+        let rules = HerdRules::default();
+        let grid = parse_input("...>>>>>...");
+        let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+
+        let (outcome, stats) = simulate(&mut automaton, &rules, Some(2));
+        assert_eq!(Outcome::StepLimit, outcome);
+        assert_eq!(
+            vec![
+                StepStats { moves: vec![1, 0] },
+                StepStats { moves: vec![2, 0] },
+            ],
+            stats
+        );
+        assert_eq!("...>>>.>.>.", to_str(&automaton.to_rows()));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let rules = HerdRules::default();
+        // A lone cucumber of each herd keeps going round forever, once the
+        // eastbound one has got out of the southbound one's way
+        let grid = parse_input(">..\n...\n.v.");
+        let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+
+        let (outcome, stats) = simulate(&mut automaton, &rules, None);
+        assert_eq!(
+            Outcome::Cycle {
+                start: 1,
+                period: 3
+            },
+            outcome
+        );
+        assert_eq!(StepStats { moves: vec![1, 0] }, stats[0]);
+        assert_eq!(4, stats.len());
+
+        assert_eq!(
+            ".>.\n...\n.v.",
+            to_str(&state_at(&grid, &rules, 1).unwrap())
+        );
+        assert_eq!(
+            state_at(&grid, &rules, 1).unwrap(),
+            state_at(&grid, &rules, 3_000_000_001).unwrap()
+        );
+        assert_eq!(
+            state_at(&grid, &rules, 2).unwrap(),
+            state_at(&grid, &rules, 1_000_000_001).unwrap()
+        );
+
+        // An animation shows steps 0 to 3 and stops there, as step 4 is step 1
+        // again
+        assert_eq!(3, last_distinct_step(&grid, &rules).unwrap());
+        let sample = parse_input(SAMPLE_INPUT);
+        assert_eq!(57, last_distinct_step(&sample, &rules).unwrap());
+    }
+
+    #[test]
+    fn test_state_at() {
+        let rules = HerdRules::default();
+        let grid = parse_input(SAMPLE_INPUT);
+
+        let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+        for _ in 0..10 {
+            run_iteration(&mut automaton, &rules);
+        }
+        assert_eq!(automaton.to_rows(), state_at(&grid, &rules, 10).unwrap());
+
+        let expected = "..>>v>vv..
+..v.>>vv..
+..>>v>>vv.
+..>>>>>vv.
+v......>vv
+v>v....>>v
+vvv.....>>
+>vv......>
+.>v.vv.v..";
+        assert_eq!(expected, to_str(&state_at(&grid, &rules, 58).unwrap()));
+        assert_eq!(expected, to_str(&state_at(&grid, &rules, 1000).unwrap()));
+    }
+
+    #[test]
+    fn test_herd_rules() {
+        assert_eq!(
+            HerdRules::default(),
+            HerdRules::new(">:east,v:south", '.').unwrap()
+        );
+        // Phases go by number, not by the order herds are listed in
+        let rules = HerdRules::new("v:s:7,>:e:3", '.').unwrap();
+        assert_eq!(vec![vec![1], vec![0]], rules.phases);
+
+        let rules = HerdRules::new("a:n:2,b:w:0,c:e:2", '_').unwrap();
+        assert_eq!(vec![vec![1], vec![0, 2]], rules.phases);
+
+        // Herds without a phase come after all the herds with one
+        let rules = HerdRules::new(">:e:1,v:s", '.').unwrap();
+        assert_eq!(vec![vec![0], vec![1]], rules.phases);
+        assert_eq!(2, rules.herds[1].phase);
+        let rules = HerdRules::new("a:n,b:w:0,c:e,d:s:0", '.').unwrap();
+        assert_eq!(vec![vec![1, 3], vec![0], vec![2]], rules.phases);
+
+        for spec in [
+            "",
+            ">",
+            ">:up",
+            ">:east:x",
+            ">:east,>:south",
+            ">:east:18446744073709551615,v:south",
+            ".:east",
+            "ab:east",
+        ] {
+            assert!(HerdRules::new(spec, '.').is_err(), "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn test_four_herds() {
+        let rules = HerdRules::new(">:east,v:south,<:west,^:north", '.').unwrap();
+        let grid = parse_input("....\n.^<.\n.>v.\n....");
+        let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+
+        let stats = run_iteration(&mut automaton, &rules);
+        assert_eq!(vec![0, 1, 0, 1], stats.moves);
+        assert_eq!(".^..\n..<.\n.>..\n..v.", to_str(&automaton.to_rows()));
+
+        let stats = run_iteration(&mut automaton, &rules);
+        assert_eq!(vec![1, 1, 1, 1], stats.moves);
+        assert_eq!("..v.\n.<..\n..>.\n.^..", to_str(&automaton.to_rows()));
+    }
+
+    #[test]
+    fn test_simultaneous() {
+        // Moving one after the other, the southbound cucumber takes the cell
+        // the eastbound one has just left; moving together, it's blocked
+        let grid = parse_input(".v.\n.>.\n...");
+        let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+        run_iteration(&mut automaton, &HerdRules::default());
+        assert_eq!("...\n.v>\n...", to_str(&automaton.to_rows()));
+
+        let together = HerdRules::new(">:east:0,v:south:0", '.').unwrap();
+        let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+        let stats = run_iteration(&mut automaton, &together);
+        assert_eq!(vec![1, 0], stats.moves);
+        assert_eq!(".v.\n..>\n...", to_str(&automaton.to_rows()));
+
+        // When two cucumbers go for the same cell, the herd listed first wins
+        let grid = parse_input(".v.\n>..\n...");
+        for (spec, moves, expected) in [
+            (">:e:0,v:s:0", vec![1, 0], ".v.\n.>.\n..."),
+            ("v:s:0,>:e:0", vec![1, 0], "...\n>v.\n..."),
+        ] {
+            let rules = HerdRules::new(spec, '.').unwrap();
+            let mut automaton = Automaton::new(&grid, Boundary::Toroidal).unwrap();
+            assert_eq!(moves, run_iteration(&mut automaton, &rules).moves);
+            assert_eq!(expected, to_str(&automaton.to_rows()));
+        }
+    }
+}
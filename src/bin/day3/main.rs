@@ -1,71 +1,240 @@
 use std::error::Error;
 
+mod stats;
+mod trie;
+
+// A report entry of any width, most significant bit first
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Bits(Vec<bool>);
+
+impl Bits {
+    fn parse(line: &str) -> Bits {
+        Bits(line.chars().map(|c| c == '1').collect())
+    }
+
+    // The low `width` bits of `value`
+    fn from_u128(value: u128, width: usize) -> Bits {
+        Bits(
+            (0..width)
+                .rev()
+                .map(|pos| (value >> pos) & 1 == 1)
+                .collect(),
+        )
+    }
+
+    fn complement(&self) -> Bits {
+        Bits(self.0.iter().map(|b| !b).collect())
+    }
+
+    // Decimal digits, least significant first
+    fn to_decimal_digits(&self) -> Vec<u32> {
+        let mut digits = vec![0];
+        for bit in self.0.iter() {
+            let mut carry = *bit as u32;
+            for d in digits.iter_mut() {
+                let v = *d * 2 + carry;
+                *d = v % 10;
+                carry = v / 10;
+            }
+            if carry > 0 {
+                digits.push(carry);
+            }
+        }
+        digits
+    }
+}
+
+// The product of two entries in decimal, which is exact however wide they are
+fn product(a: &Bits, b: &Bits) -> String {
+    let (a, b) = (a.to_decimal_digits(), b.to_decimal_digits());
+    let mut digits = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, y) in b.iter().enumerate() {
+            let v = digits[i + j] + x * y + carry;
+            digits[i + j] = v % 10;
+            carry = v / 10;
+        }
+        digits[i + b.len()] += carry;
+    }
+
+    while digits.len() > 1 && digits.last() == Some(&0) {
+        digits.pop();
+    }
+    digits.iter().rev().map(|d| d.to_string()).collect()
+}
+
+// Which bit to keep at a position, given how many of the entries have a 1
+// there and how many a 0
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct BitCriteria {
+    most_common: bool,
+    on_tie: bool,
+}
+
+impl BitCriteria {
+    fn select(&self, ones: usize, zeros: usize) -> bool {
+        if ones == zeros {
+            self.on_tie
+        } else {
+            (ones > zeros) == self.most_common
+        }
+    }
+}
+
+// The puzzle doesn't say what the gamma rate does on a tie; it takes a 0,
+// which makes epsilon take a 1
+const GAMMA: BitCriteria = BitCriteria {
+    most_common: true,
+    on_tie: false,
+};
+const OXYGEN: BitCriteria = BitCriteria {
+    most_common: true,
+    on_tie: true,
+};
+const CO2: BitCriteria = BitCriteria {
+    most_common: false,
+    on_tie: false,
+};
+
+// What went wrong when filtering by bit criteria leaves no single line,
+// whether the lines are filtered here or in the trie
+fn no_lines_left(bits: usize) -> String {
+    format!(
+        "No lines are left after {} bits, so there's no rating",
+        bits
+    )
+}
+
+fn identical_lines(n: usize) -> String {
+    format!(
+        "{} identical lines are left after every bit, so there's no single rating",
+        n
+    )
+}
+
+// Checks that every line is made of 0s and 1s and is as long as the first,
+// returning that width
+fn validate(lines: &[&str]) -> Result<usize, Box<dyn Error>> {
+    let width = lines.first().ok_or("Empty input")?.chars().count();
+    if width == 0 {
+        return Err("Line 1 is empty".into());
+    }
+
+    for (i, l) in lines.iter().enumerate() {
+        if let Some(c) = l.chars().find(|c| *c != '0' && *c != '1') {
+            return Err(format!("Invalid character {:?} on line {}", c, i + 1).into());
+        }
+        let len = l.chars().count();
+        if len != width {
+            return Err(format!(
+                "Uneven line lengths! Line {} has {} bits, line 1 has {}",
+                i + 1,
+                len,
+                width
+            )
+            .into());
+        }
+    }
+
+    Ok(width)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let input = aoc2021::get_input_string()?;
     let mut lines = input.lines().collect::<Vec<_>>();
-    lines.sort();
+    let width = validate(&lines)?;
+
+    if aoc2021::has_flag("stats") {
+        print!("{}", stats::ColumnStats::new(&lines));
+        return Ok(());
+    }
+    if aoc2021::has_flag("trace") {
+        for (name, criteria) in [("Oxygen generator", OXYGEN), ("CO2 scrubber", CO2)] {
+            println!("{} rating:", name);
+            print!(
+                "{}",
+                stats::filter(&lines, |_, ones, zeros| criteria.select(ones, zeros))
+            );
+        }
+        return Ok(());
+    }
+
     let (gamma, epsilon) = calc_gamma_epsilon(&lines)?;
 
-    println!("Part 1: {}", gamma * epsilon);
+    println!("Part 1: {}", product(&gamma, &epsilon));
 
-    let oxygen_gen_rating = calc_life_support_rating(&lines, true);
-    let co2_scrbber_rating = calc_life_support_rating(&lines, false);
+    // Reports too wide for the trie fall back to sorting the lines
+    let (oxygen_gen_rating, co2_scrbber_rating) = if width <= 128 {
+        let trie = trie::Trie::from_lines(&lines)?;
+        (
+            Bits::from_u128(trie.rating(OXYGEN)?, width),
+            Bits::from_u128(trie.rating(CO2)?, width),
+        )
+    } else {
+        lines.sort();
+        (
+            calc_life_support_rating(&lines, OXYGEN)?,
+            calc_life_support_rating(&lines, CO2)?,
+        )
+    };
 
-    println!("Part 2: {}", oxygen_gen_rating * co2_scrbber_rating);
+    println!(
+        "Part 2: {}",
+        product(&oxygen_gen_rating, &co2_scrbber_rating)
+    );
 
     Ok(())
 }
 
-fn calc_life_support_rating(sorted_lines: &[&str], most_common: bool) -> u32 {
-    let mut candidates = &sorted_lines[..];
+// The lines must be validated and sorted
+fn calc_life_support_rating(
+    sorted_lines: &[&str],
+    criteria: BitCriteria,
+) -> Result<Bits, Box<dyn Error>> {
+    let mut candidates = sorted_lines;
     let mut pos = 0;
-    while candidates.len() != 1 {
-        let majority_count = candidates.len() / 2;
-        let num_zeros =
-            candidates.partition_point(|l| l.chars().nth(pos).expect("No match found!") == '0');
+    while candidates.len() > 1 {
+        if pos == candidates[0].len() {
+            return Err(identical_lines(candidates.len()).into());
+        }
 
-        let (majority, minority) = if most_common {
-            (&candidates[0..num_zeros], &candidates[num_zeros..])
-        } else {
-            (&candidates[num_zeros..], &candidates[0..num_zeros])
-        };
+        let num_zeros = candidates.partition_point(|l| l.as_bytes()[pos] == b'0');
+        let num_ones = candidates.len() - num_zeros;
 
-        if num_zeros > majority_count {
-            candidates = majority;
+        candidates = if criteria.select(num_ones, num_zeros) {
+            &candidates[num_zeros..]
         } else {
-            candidates = minority;
-        }
+            &candidates[..num_zeros]
+        };
 
         pos += 1;
+        if candidates.is_empty() {
+            return Err(no_lines_left(pos).into());
+        }
     }
 
-    u32::from_str_radix(candidates[0], 2).unwrap()
+    Ok(Bits::parse(candidates.first().ok_or("Empty input")?))
 }
 
-fn calc_gamma_epsilon(lines: &[&str]) -> Result<(u32, u32), Box<dyn Error>> {
-    let binary_len = lines.get(0).ok_or("Empty input")?.len();
+fn calc_gamma_epsilon(lines: &[&str]) -> Result<(Bits, Bits), Box<dyn Error>> {
+    let binary_len = validate(lines)?;
     let mut bit_counts: Vec<usize> = vec![0; binary_len];
     for l in lines {
-        if l.len() > binary_len {
-            return Err("Uneven line lengths!".into());
-        }
-
-        for (i, b) in l.chars().enumerate() {
-            match b {
-                '1' => bit_counts[i] += 1,
-                '0' => (),
-                _ => return Err("Invalid character".into()),
+        for (i, b) in l.bytes().enumerate() {
+            if b == b'1' {
+                bit_counts[i] += 1;
             }
         }
     }
 
-    let majority_count = (lines.len() / 2) as u32;
-    let gamma_rate: u32 = bit_counts.iter().fold(0, |acc, count| {
-        let bit = if *count as u32 > majority_count { 1 } else { 0 };
-        (acc << 1) + bit
-    });
-
-    let epsilon_rate = ((!gamma_rate) << (32 - binary_len)) >> (32 - binary_len);
+    let gamma_rate = Bits(
+        bit_counts
+            .iter()
+            .map(|ones| GAMMA.select(*ones, lines.len() - ones))
+            .collect(),
+    );
+    let epsilon_rate = gamma_rate.complement();
 
     Ok((gamma_rate, epsilon_rate))
 }
@@ -74,35 +243,109 @@ fn calc_gamma_epsilon(lines: &[&str]) -> Result<(u32, u32), Box<dyn Error>> {
 mod tests {
     use super::*;
 
-    const SAMPLE_INPUT: &str = "00100
-11110
-10110
-10111
-10101
-01111
-00111
-11100
-10000
-11001
-00010
-01010";
+    const SAMPLE_INPUT: &str = include_str!("./sample.txt");
 
     #[test]
     fn test_calc_gamma_epsilon() {
         let lines = SAMPLE_INPUT.lines().collect::<Vec<_>>();
         let (gamma, epsilon) = calc_gamma_epsilon(&lines).unwrap();
-        assert_eq!(gamma, 0b10110);
-        assert_eq!(epsilon, 0b01001);
+        assert_eq!(gamma, Bits::parse("10110"));
+        assert_eq!(epsilon, Bits::parse("01001"));
+        assert_eq!("198", product(&gamma, &epsilon));
     }
 
     #[test]
     fn test_calc_life_support_rating() {
         let mut lines = SAMPLE_INPUT.lines().collect::<Vec<_>>();
         lines.sort();
-        let oxygen_rating = calc_life_support_rating(&lines, true);
-        assert_eq!(oxygen_rating, 0b10111);
+        let oxygen_rating = calc_life_support_rating(&lines, OXYGEN).unwrap();
+        assert_eq!(oxygen_rating, Bits::parse("10111"));
+
+        let co2_scrubber_rating = calc_life_support_rating(&lines, CO2).unwrap();
+        assert_eq!(co2_scrubber_rating, Bits::parse("01010"));
+        assert_eq!("230", product(&oxygen_rating, &co2_scrubber_rating));
+    }
+
+    #[test]
+    fn test_ties() {
+        assert!(OXYGEN.select(2, 2));
+        assert!(!CO2.select(2, 2));
+        assert!(!GAMMA.select(2, 2));
+        assert!(OXYGEN.select(3, 2) && !OXYGEN.select(2, 3));
+        assert!(!CO2.select(3, 2) && CO2.select(2, 3));
 
-        let co2_scrubber_rating = calc_life_support_rating(&lines, false);
-        assert_eq!(co2_scrubber_rating, 0b01010);
+        // Every position is a tie, so oxygen keeps taking the 1s and CO2 the
+        // 0s all the way down
+        let mut lines = vec!["00", "01", "10", "11"];
+        lines.sort();
+        assert_eq!(
+            Bits::parse("11"),
+            calc_life_support_rating(&lines, OXYGEN).unwrap()
+        );
+        assert_eq!(
+            Bits::parse("00"),
+            calc_life_support_rating(&lines, CO2).unwrap()
+        );
+        let (gamma, epsilon) = calc_gamma_epsilon(&lines).unwrap();
+        assert_eq!((Bits::parse("00"), Bits::parse("11")), (gamma, epsilon));
+
+        assert!(calc_life_support_rating(&["01", "01"], OXYGEN).is_err());
+    }
+
+    #[test]
+    fn test_wide_report() {
+        // 100 bits: gamma is 1 followed by 99 0s, epsilon 0 followed by 99 1s
+        let one = format!("1{}", "0".repeat(99));
+        let odd = format!("1{}1", "0".repeat(98));
+        let zero = "0".repeat(100);
+        let mut lines = vec![one.as_str(), odd.as_str(), zero.as_str()];
+        let (gamma, epsilon) = calc_gamma_epsilon(&lines).unwrap();
+        assert_eq!(Bits::parse(&one), gamma);
+        assert_eq!(zero.len(), epsilon.0.len());
+
+        // 2^99 * (2^99 - 1)
+        assert_eq!(
+            "401734511064747568885490523084656825330436633744949857222656",
+            product(&gamma, &epsilon)
+        );
+
+        // Oxygen keeps the two lines starting with 1, then the tie on the last
+        // bit goes to `odd`
+        lines.sort();
+        assert_eq!(
+            Bits::parse(&odd),
+            calc_life_support_rating(&lines, OXYGEN).unwrap()
+        );
+        assert_eq!(
+            Bits::parse(&zero),
+            calc_life_support_rating(&lines, CO2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(
+            5,
+            validate(&SAMPLE_INPUT.lines().collect::<Vec<_>>()).unwrap()
+        );
+
+        for lines in [vec!["0101", "010"], vec!["010", "0101"]] {
+            let err = validate(&lines).unwrap_err().to_string();
+            assert!(err.starts_with("Uneven line lengths!"), "{}", err);
+            assert!(calc_gamma_epsilon(&lines).is_err());
+        }
+
+        assert!(validate(&[]).is_err());
+        assert!(validate(&[""]).is_err());
+        assert!(validate(&["012"]).is_err());
+    }
+
+    #[test]
+    fn test_product() {
+        assert_eq!("0", product(&Bits::parse("0"), &Bits::parse("111")));
+        assert_eq!(
+            (u64::MAX as u128 * u64::MAX as u128).to_string(),
+            product(&Bits::parse(&"1".repeat(64)), &Bits::parse(&"1".repeat(64)))
+        );
     }
 }
@@ -0,0 +1,255 @@
+use std::fmt;
+
+// Column statistics over a report whose lines have been validated, bit 0
+// being the leftmost column
+pub struct ColumnStats<'a> {
+    lines: &'a [&'a str],
+    ones: Vec<usize>,
+}
+
+fn bit(line: &str, pos: usize) -> bool {
+    line.as_bytes()[pos] == b'1'
+}
+
+impl<'a> ColumnStats<'a> {
+    pub fn new(lines: &'a [&'a str]) -> ColumnStats<'a> {
+        let width = lines.first().map_or(0, |l| l.len());
+        let ones = (0..width)
+            .map(|pos| lines.iter().filter(|l| bit(l, pos)).count())
+            .collect();
+        ColumnStats { lines, ones }
+    }
+
+    pub fn width(&self) -> usize {
+        self.ones.len()
+    }
+
+    pub fn ones(&self, pos: usize) -> usize {
+        self.ones[pos]
+    }
+
+    pub fn zeros(&self, pos: usize) -> usize {
+        self.lines.len() - self.ones[pos]
+    }
+
+    // Shannon entropy of the column in bits: 0 when it never changes, 1 when
+    // it's an even split
+    pub fn entropy(&self, pos: usize) -> f64 {
+        let n = self.lines.len() as f64;
+        [self.ones(pos), self.zeros(pos)]
+            .iter()
+            .filter(|c| **c > 0)
+            .map(|c| {
+                let p = *c as f64 / n;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    // The phi coefficient of two columns, from -1 when one is always the
+    // opposite of the other to 1 when they're always the same. A column that
+    // never changes has no correlation with anything, so that's None.
+    pub fn correlation(&self, a: usize, b: usize) -> Option<f64> {
+        let n = self.lines.len() as f64;
+        let both = self.lines.iter().filter(|l| bit(l, a) && bit(l, b)).count() as f64;
+        let (ones_a, ones_b) = (self.ones(a) as f64, self.ones(b) as f64);
+
+        let spread = ones_a * (n - ones_a) * ones_b * (n - ones_b);
+        if spread == 0.0 {
+            return None;
+        }
+        Some((n * both - ones_a * ones_b) / spread.sqrt())
+    }
+}
+
+impl fmt::Display for ColumnStats<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "bit   ones  zeros  entropy")?;
+        for pos in 0..self.width() {
+            writeln!(
+                f,
+                "{:>3} {:>6} {:>6} {:>8.3}",
+                pos,
+                self.ones(pos),
+                self.zeros(pos),
+                self.entropy(pos)
+            )?;
+        }
+
+        writeln!(f, "\nCorrelations:")?;
+        write!(f, "   ")?;
+        for b in 0..self.width() {
+            write!(f, " {:>5}", b)?;
+        }
+        writeln!(f)?;
+        for a in 0..self.width() {
+            write!(f, "{:>3}", a)?;
+            for b in 0..self.width() {
+                match self.correlation(a, b) {
+                    Some(r) => write!(f, " {:>5.2}", r)?,
+                    None => write!(f, " {:>5}", "-")?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+// One round of filtering: at bit `pos` the candidates had `ones` 1s and
+// `zeros` 0s, the ones with bit `kept` stayed and `remaining` were left
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterStep {
+    pos: usize,
+    ones: usize,
+    zeros: usize,
+    kept: bool,
+    remaining: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filtering<'a> {
+    steps: Vec<FilterStep>,
+    survivors: Vec<&'a str>,
+}
+
+// Narrows the lines down a bit at a time, left to right, the way the life
+// support ratings are found. At each position `keep(pos, ones, zeros)` picks
+// which bit the candidates must have there. It stops when one line is left or
+// the bits run out, so more than one can survive if there are duplicates.
+pub fn filter<'a, F>(lines: &[&'a str], mut keep: F) -> Filtering<'a>
+where
+    F: FnMut(usize, usize, usize) -> bool,
+{
+    let width = lines.first().map_or(0, |l| l.len());
+    let mut survivors = lines.to_vec();
+    let mut steps = Vec::new();
+
+    for pos in 0..width {
+        if survivors.len() <= 1 {
+            break;
+        }
+
+        let ones = survivors.iter().filter(|l| bit(l, pos)).count();
+        let zeros = survivors.len() - ones;
+        let kept = keep(pos, ones, zeros);
+        survivors.retain(|l| bit(l, pos) == kept);
+        steps.push(FilterStep {
+            pos,
+            ones,
+            zeros,
+            kept,
+            remaining: survivors.len(),
+        });
+    }
+
+    Filtering { steps, survivors }
+}
+
+impl fmt::Display for Filtering<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for s in self.steps.iter() {
+            writeln!(
+                f,
+                "  bit {}: {} ones, {} zeros, keeping {}s -> {} left",
+                s.pos, s.ones, s.zeros, s.kept as u8, s.remaining
+            )?;
+        }
+        match self.survivors.as_slice() {
+            [line] => writeln!(f, "  rating: {}", line),
+            lines => writeln!(f, "  {} lines left", lines.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{calc_life_support_rating, Bits, CO2, OXYGEN};
+
+    const SAMPLE_INPUT: &str = include_str!("./sample.txt");
+
+    #[test]
+    fn test_column_stats() {
+        let lines = SAMPLE_INPUT.lines().collect::<Vec<_>>();
+        let stats = ColumnStats::new(&lines);
+        assert_eq!(5, stats.width());
+        assert_eq!(
+            vec![7, 5, 8, 7, 5],
+            (0..5).map(|p| stats.ones(p)).collect::<Vec<_>>()
+        );
+        assert_eq!(5, stats.zeros(0));
+
+        assert!((stats.entropy(0) - 0.9799).abs() < 1e-4);
+        let lines = ["01", "01", "11", "10"];
+        let stats = ColumnStats::new(&lines);
+        assert_eq!(1.0, stats.entropy(0));
+        assert!((stats.entropy(1) - 0.8113).abs() < 1e-4);
+        assert_eq!(0.0, ColumnStats::new(&["1", "1"]).entropy(0));
+    }
+
+    #[test]
+    fn test_correlation() {
+        // Bit 1 copies bit 0, bit 2 is the opposite of bit 0 and bit 3 never
+        // changes
+        let lines = ["0010", "1100", "0010", "1100"];
+        let stats = ColumnStats::new(&lines);
+        assert_eq!(Some(1.0), stats.correlation(0, 0));
+        assert_eq!(Some(1.0), stats.correlation(0, 1));
+        assert_eq!(Some(-1.0), stats.correlation(0, 2));
+        assert_eq!(None, stats.correlation(0, 3));
+
+        let lines = ["00", "01", "10", "11"];
+        assert_eq!(Some(0.0), ColumnStats::new(&lines).correlation(0, 1));
+
+        let lines = SAMPLE_INPUT.lines().collect::<Vec<_>>();
+        let stats = ColumnStats::new(&lines);
+        for a in 0..5 {
+            for b in 0..5 {
+                let r = stats.correlation(a, b).unwrap();
+                assert!((-1.0..=1.0).contains(&r));
+                assert_eq!(Some(r), stats.correlation(b, a));
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let lines = SAMPLE_INPUT.lines().collect::<Vec<_>>();
+        let mut sorted = lines.clone();
+        sorted.sort();
+
+        for criteria in [OXYGEN, CO2] {
+            let filtering = filter(&lines, |_, ones, zeros| criteria.select(ones, zeros));
+            assert_eq!(
+                calc_life_support_rating(&sorted, criteria).unwrap(),
+                Bits::parse(filtering.survivors[0])
+            );
+        }
+
+        let oxygen = filter(&lines, |_, ones, zeros| OXYGEN.select(ones, zeros));
+        assert_eq!(
+            vec![7, 4, 3, 2, 1],
+            oxygen.steps.iter().map(|s| s.remaining).collect::<Vec<_>>()
+        );
+        assert!(oxygen
+            .to_string()
+            .starts_with("  bit 0: 7 ones, 5 zeros, keeping 1s -> 7 left\n"));
+        assert!(oxygen.to_string().ends_with("  rating: 10111\n"));
+
+        // Any predicate goes: keep whatever bit the position's parity says
+        let parity = filter(&lines, |pos, _, _| pos % 2 == 1);
+        assert_eq!(vec!["01010"], parity.survivors);
+
+        // Duplicates can't be told apart
+        let dups = filter(&["101", "101", "000"], |_, ones, zeros| ones >= zeros);
+        assert_eq!(vec!["101", "101"], dups.survivors);
+        assert!(dups.to_string().ends_with("  2 lines left\n"));
+
+        // Asking for a bit none of them have leaves nothing
+        let none = filter(&["101", "100"], |_, _, _| false);
+        assert!(none.survivors.is_empty());
+        assert!(none.to_string().ends_with("  0 lines left\n"));
+    }
+}
@@ -0,0 +1,221 @@
+use super::{identical_lines, no_lines_left, BitCriteria};
+use std::error::Error;
+
+// A binary trie over the report's numbers, most significant bit first, where
+// each node counts the numbers below it. The life support ratings are then a
+// walk from the root that looks at the two children's counts at each level,
+// O(width) however long the report is.
+pub struct Trie {
+    width: usize,
+    nodes: Vec<Node>,
+}
+
+// Child 0 means no child, as the root can't be anyone's child
+#[derive(Clone, Default)]
+struct Node {
+    count: usize,
+    children: [u32; 2],
+}
+
+impl Trie {
+    pub fn new(width: usize) -> Result<Trie, String> {
+        if width == 0 || width > 128 {
+            return Err(format!(
+                "Lines of {} bits can't be read as 128-bit numbers",
+                width
+            ));
+        }
+        Ok(Trie {
+            width,
+            nodes: vec![Node::default()],
+        })
+    }
+
+    // The lines must be validated
+    pub fn from_lines(lines: &[&str]) -> Result<Trie, Box<dyn Error>> {
+        let mut trie = Trie::new(lines.first().map_or(0, |l| l.len()))?;
+        for l in lines {
+            trie.insert(u128::from_str_radix(l, 2)?);
+        }
+        Ok(trie)
+    }
+
+    pub fn insert(&mut self, value: u128) {
+        let mut node = 0;
+        self.nodes[0].count += 1;
+        for pos in (0..self.width).rev() {
+            let b = ((value >> pos) & 1) as usize;
+            if self.nodes[node].children[b] == 0 {
+                self.nodes[node].children[b] = self.nodes.len() as u32;
+                self.nodes.push(Node::default());
+            }
+            node = self.nodes[node].children[b] as usize;
+            self.nodes[node].count += 1;
+        }
+    }
+
+    fn count(&self, node: usize, b: usize) -> usize {
+        match self.nodes[node].children[b] {
+            0 => 0,
+            child => self.nodes[child as usize].count,
+        }
+    }
+
+    // Same as `calc_life_support_rating` in main.rs
+    pub fn rating(&self, criteria: BitCriteria) -> Result<u128, Box<dyn Error>> {
+        let mut node = 0;
+        let mut value = 0;
+        for pos in 0..self.width {
+            let (zeros, ones) = (self.count(node, 0), self.count(node, 1));
+            let b = match zeros + ones {
+                0 => return Err(no_lines_left(pos).into()),
+                // Once there's one candidate it's the rating, whatever its
+                // remaining bits are
+                1 => (ones == 1) as usize,
+                _ => criteria.select(ones, zeros) as usize,
+            };
+            node = self.nodes[node].children[b] as usize;
+            if node == 0 {
+                return Err(no_lines_left(pos + 1).into());
+            }
+            value = (value << 1) | b as u128;
+        }
+
+        match self.nodes[node].count {
+            1 => Ok(value),
+            n => Err(identical_lines(n).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{calc_life_support_rating, Bits, CO2, OXYGEN};
+    use aoc2021::testing::Rng;
+    use std::time::Instant;
+
+    const SAMPLE_INPUT: &str = include_str!("./sample.txt");
+
+    fn random_report(rng: &mut Rng, lines: usize, width: usize) -> Vec<String> {
+        (0..lines)
+            .map(|_| {
+                (0..width)
+                    .map(|_| if rng.next_u64() & 1 == 1 { '1' } else { '0' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn sorted_ratings(lines: &[&str]) -> Vec<Result<Bits, String>> {
+        let mut sorted = lines.to_vec();
+        sorted.sort();
+        [OXYGEN, CO2]
+            .iter()
+            .map(|c| calc_life_support_rating(&sorted, *c).map_err(|e| e.to_string()))
+            .collect()
+    }
+
+    fn trie_ratings(lines: &[&str]) -> Vec<Result<Bits, String>> {
+        let trie = Trie::from_lines(lines).unwrap();
+        [OXYGEN, CO2]
+            .iter()
+            .map(|c| {
+                trie.rating(*c)
+                    .map(|v| Bits::from_u128(v, trie.width))
+                    .map_err(|e| e.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sample() {
+        let lines = SAMPLE_INPUT.lines().collect::<Vec<_>>();
+        let trie = Trie::from_lines(&lines).unwrap();
+        assert_eq!(23, trie.rating(OXYGEN).unwrap());
+        assert_eq!(10, trie.rating(CO2).unwrap());
+        assert_eq!(12, trie.nodes[0].count);
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        assert!(Trie::new(0).is_err());
+        assert!(Trie::new(129).is_err());
+
+        // 128 bits still fit
+        let one = format!("1{}", "0".repeat(127));
+        let zero = "0".repeat(128);
+        let lines = [one.as_str(), zero.as_str()];
+        let trie = Trie::from_lines(&lines).unwrap();
+        assert_eq!(1 << 127, trie.rating(OXYGEN).unwrap());
+        assert_eq!(0, trie.rating(CO2).unwrap());
+
+        // Everyone has a 1 first, so there's no 0 for CO2 to keep
+        for lines in [vec!["10", "11"], vec!["01", "01"], vec!["1"]] {
+            assert_eq!(sorted_ratings(&lines), trie_ratings(&lines));
+        }
+        assert_eq!(
+            Err(no_lines_left(1)),
+            trie_ratings(&["10", "11"])[1].clone()
+        );
+        assert_eq!(
+            Err(identical_lines(2)),
+            trie_ratings(&["01", "01"])[0].clone()
+        );
+    }
+
+    #[test]
+    fn test_agrees_with_sorted() {
+        let mut rng = Rng(0xd1a6);
+        for (lines, width) in [(1, 1), (2, 3), (12, 5), (100, 4), (1000, 12), (500, 70)] {
+            for _ in 0..5 {
+                let report = random_report(&mut rng, lines, width);
+                let report = report.iter().map(String::as_str).collect::<Vec<_>>();
+                assert_eq!(sorted_ratings(&report), trie_ratings(&report));
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_trie_vs_sorted() {
+        let mut rng = Rng(0xbe4c3);
+        for (lines, width) in [(1000, 12), (1_000_000, 32), (2_000_000, 24)] {
+            let report = random_report(&mut rng, lines, width);
+            let report = report.iter().map(String::as_str).collect::<Vec<_>>();
+
+            // Setting up (sorting or building the trie) and finding the two
+            // ratings are timed separately
+            let start = Instant::now();
+            let mut sorted = report.clone();
+            sorted.sort();
+            let sort_elapsed = start.elapsed();
+            let start = Instant::now();
+            let sorted_result = [OXYGEN, CO2]
+                .iter()
+                .map(|c| calc_life_support_rating(&sorted, *c).map_err(|e| e.to_string()))
+                .collect::<Vec<_>>();
+            let sorted_elapsed = start.elapsed();
+
+            let start = Instant::now();
+            let trie = Trie::from_lines(&report).unwrap();
+            let build_elapsed = start.elapsed();
+            let start = Instant::now();
+            let trie_result = [OXYGEN, CO2]
+                .iter()
+                .map(|c| {
+                    trie.rating(*c)
+                        .map(|v| Bits::from_u128(v, width))
+                        .map_err(|e| e.to_string())
+                })
+                .collect::<Vec<_>>();
+            let trie_elapsed = start.elapsed();
+
+            assert_eq!(sorted_result, trie_result);
+            println!(
+                "{} lines of {} bits: sort {:?} + ratings {:?}, trie build {:?} + ratings {:?}",
+                lines, width, sort_elapsed, sorted_elapsed, build_elapsed, trie_elapsed
+            );
+        }
+    }
+}
@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+// A rectangular board of `rows` x `cols` numbers, stored row by row.
+// `index` is where the board came in the input, counting boards that were
+// skipped, so that it can be reported as the board's number.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Board {
+    index: usize,
+    rows: usize,
+    cols: usize,
+    cells: Vec<u32>,
+    coords: HashMap<u32, (usize, usize)>,
+}
+
+impl Board {
+    pub fn new(index: usize, grid: Vec<Vec<u32>>) -> Result<Board, String> {
+        let rows = grid.len();
+        let cols = grid.first().map_or(0, |row| row.len());
+        if rows == 0 || cols == 0 {
+            return Err("A board can't be empty".into());
+        }
+        if grid.iter().any(|row| row.len() != cols) {
+            return Err("Board rows have different lengths".into());
+        }
+
+        let cells = grid.into_iter().flatten().collect::<Vec<_>>();
+        let mut coords = HashMap::with_capacity(cells.len());
+        for (i, v) in cells.iter().enumerate() {
+            if coords.insert(*v, (i / cols, i % cols)).is_some() {
+                return Err(format!("{} is on the board more than once", v));
+            }
+        }
+
+        Ok(Board {
+            index,
+            rows,
+            cols,
+            cells,
+            coords,
+        })
+    }
+
+    pub fn position(&self, number: u32) -> Option<(usize, usize)> {
+        self.coords.get(&number).copied()
+    }
+
+    // The cells, as indices into `cells`, that each line of the patterns
+    // needs marked
+    fn lines(&self, patterns: &[Pattern]) -> Vec<(WinLine, Vec<usize>)> {
+        let (rows, cols) = (self.rows, self.cols);
+        let mut lines = Vec::new();
+
+        for pattern in patterns {
+            match pattern {
+                Pattern::Rows => lines.extend(
+                    (0..rows).map(|r| (WinLine::Row(r), (0..cols).map(|c| r * cols + c).collect())),
+                ),
+                Pattern::Columns => lines.extend((0..cols).map(|c| {
+                    (
+                        WinLine::Column(c),
+                        (0..rows).map(|r| r * cols + c).collect(),
+                    )
+                })),
+                // Only square boards have corner-to-corner diagonals
+                Pattern::Diagonals if rows == cols => {
+                    lines.push((WinLine::Diagonal, (0..rows).map(|i| i * cols + i).collect()));
+                    lines.push((
+                        WinLine::AntiDiagonal,
+                        (0..rows).map(|i| i * cols + cols - 1 - i).collect(),
+                    ));
+                }
+                Pattern::Diagonals => (),
+                Pattern::Corners => {
+                    let mut corners = vec![0, cols - 1, (rows - 1) * cols, rows * cols - 1];
+                    corners.sort();
+                    corners.dedup();
+                    lines.push((WinLine::Corners, corners));
+                }
+                Pattern::FullCard => lines.push((WinLine::FullCard, (0..rows * cols).collect())),
+            }
+        }
+
+        lines
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Rows,
+    Columns,
+    Diagonals,
+    Corners,
+    FullCard,
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rows" => Ok(Pattern::Rows),
+            "columns" => Ok(Pattern::Columns),
+            "diagonals" => Ok(Pattern::Diagonals),
+            "corners" => Ok(Pattern::Corners),
+            "full" => Ok(Pattern::FullCard),
+            _ => Err(format!(
+                "Unknown win pattern {:?}, expected rows, columns, diagonals, corners or full",
+                s
+            )),
+        }
+    }
+}
+
+pub const DEFAULT_PATTERNS: [Pattern; 2] = [Pattern::Rows, Pattern::Columns];
+
+// A comma separated list such as "rows,columns,corners"
+pub fn parse_patterns(s: &str) -> Result<Vec<Pattern>, String> {
+    let mut patterns = Vec::new();
+    for p in s.split(',') {
+        let p = p.trim().parse::<Pattern>()?;
+        if !patterns.contains(&p) {
+            patterns.push(p);
+        }
+    }
+    Ok(patterns)
+}
+
+// Rows and columns are 0-based, and shown 1-based
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WinLine {
+    Row(usize),
+    Column(usize),
+    Diagonal,
+    AntiDiagonal,
+    Corners,
+    FullCard,
+}
+
+impl fmt::Display for WinLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WinLine::Row(r) => write!(f, "row {}", r + 1),
+            WinLine::Column(c) => write!(f, "column {}", c + 1),
+            WinLine::Diagonal => write!(f, "the diagonal"),
+            WinLine::AntiDiagonal => write!(f, "the anti-diagonal"),
+            WinLine::Corners => write!(f, "the four corners"),
+            WinLine::FullCard => write!(f, "a full card"),
+        }
+    }
+}
+
+// The board with index `board` won when `number`, draw `draw` of the
+// drawing, completed `lines`. Both are 0-based.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Win {
+    pub draw: usize,
+    pub number: u32,
+    pub board: usize,
+    pub lines: Vec<WinLine>,
+    pub score: u64,
+}
+
+impl fmt::Display for Win {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines = self
+            .lines
+            .iter()
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>()
+            .join(" and ");
+        write!(
+            f,
+            "Draw {} ({}): board {} wins with {}, score {}",
+            self.draw + 1,
+            self.number,
+            self.board + 1,
+            lines,
+            self.score
+        )
+    }
+}
+
+// Every win in the order they happened; boards winning on the same draw are
+// in board order
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameLog {
+    pub wins: Vec<Win>,
+    boards: usize,
+}
+
+impl GameLog {
+    // The k-th board to win, counting from 1
+    pub fn kth_winner(&self, k: usize) -> Option<&Win> {
+        self.wins.get(k.checked_sub(1)?)
+    }
+
+    // The board that wins last, if every board wins at all
+    pub fn last_winner(&self) -> Option<&Win> {
+        if self.wins.len() == self.boards {
+            self.wins.last()
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for GameLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for win in self.wins.iter() {
+            writeln!(f, "{}", win)?;
+        }
+        if self.wins.len() < self.boards {
+            writeln!(f, "{} boards never win", self.boards - self.wins.len())?;
+        }
+        Ok(())
+    }
+}
+
+// How far a board is from winning
+struct Progress {
+    lines: Vec<WinLine>,
+    // Unmarked cells left in each line
+    remaining: Vec<usize>,
+    // The lines going through each cell
+    lines_at: Vec<Vec<usize>>,
+    marked: Vec<bool>,
+    unmarked_sum: u64,
+}
+
+impl Progress {
+    fn new(board: &Board, patterns: &[Pattern]) -> Progress {
+        let mut lines_at = vec![Vec::new(); board.cells.len()];
+        let mut lines = Vec::new();
+        let mut remaining = Vec::new();
+        for (i, (line, cells)) in board.lines(patterns).into_iter().enumerate() {
+            for cell in cells.iter() {
+                lines_at[*cell].push(i);
+            }
+            lines.push(line);
+            remaining.push(cells.len());
+        }
+
+        Progress {
+            lines,
+            remaining,
+            lines_at,
+            marked: vec![false; board.cells.len()],
+            unmarked_sum: board.cells.iter().map(|v| *v as u64).sum(),
+        }
+    }
+
+    // Marks a cell, returning the lines that completes
+    fn mark(&mut self, cell: usize, value: u32) -> Vec<WinLine> {
+        if self.marked[cell] {
+            return Vec::new();
+        }
+        self.marked[cell] = true;
+        self.unmarked_sum -= value as u64;
+
+        let mut completed = Vec::new();
+        for &i in self.lines_at[cell].iter() {
+            self.remaining[i] -= 1;
+            if self.remaining[i] == 0 {
+                completed.push(i);
+            }
+        }
+        completed.sort();
+        completed.into_iter().map(|i| self.lines[i]).collect()
+    }
+}
+
+// Plays the whole drawing, stopping early once every board has won. A board
+// stops playing once it has won.
+pub fn play(boards: &[Board], drawing: &[u32], patterns: &[Pattern]) -> GameLog {
+    let mut progress = boards
+        .iter()
+        .map(|b| Some(Progress::new(b, patterns)))
+        .collect::<Vec<_>>();
+    let mut wins = Vec::new();
+
+    for (draw, &number) in drawing.iter().enumerate() {
+        if wins.len() == boards.len() {
+            break;
+        }
+
+        for (i, board) in boards.iter().enumerate() {
+            let (Some(p), Some((r, c))) = (progress[i].as_mut(), board.position(number)) else {
+                continue;
+            };
+
+            let lines = p.mark(r * board.cols + c, number);
+            if !lines.is_empty() {
+                wins.push(Win {
+                    draw,
+                    number,
+                    board: board.index,
+                    lines,
+                    score: number as u64 * p.unmarked_sum,
+                });
+                progress[i] = None;
+            }
+        }
+    }
+
+    GameLog {
+        wins,
+        boards: boards.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_input;
+
+    const SAMPLE_INPUT: &str = include_str!("sample.txt");
+
+    fn board(rows: &[&[u32]]) -> Board {
+        Board::new(0, rows.iter().map(|r| r.to_vec()).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_sample_log() {
+        let (boards, drawing) = parse_input(SAMPLE_INPUT).unwrap();
+        let log = play(&boards, &drawing, &DEFAULT_PATTERNS);
+
+        assert_eq!(
+            Some(&Win {
+                draw: 11,
+                number: 24,
+                board: 2,
+                lines: vec![WinLine::Row(0)],
+                score: 4512
+            }),
+            log.kth_winner(1)
+        );
+        assert_eq!(
+            vec![2, 0, 1],
+            log.wins.iter().map(|w| w.board).collect::<Vec<_>>()
+        );
+        assert_eq!(Some(1924), log.last_winner().map(|w| w.score));
+        assert_eq!(log.kth_winner(3), log.last_winner());
+        assert_eq!(None, log.kth_winner(0));
+        assert_eq!(None, log.kth_winner(4));
+
+        assert!(log
+            .to_string()
+            .starts_with("Draw 12 (24): board 3 wins with row 1, score 4512\n"));
+    }
+
+    #[test]
+    fn test_non_square() {
+        let b = board(&[&[1, 2, 3], &[4, 5, 6]]);
+        assert_eq!((2, 3), (b.rows, b.cols));
+
+        let log = play(std::slice::from_ref(&b), &[2, 5], &DEFAULT_PATTERNS);
+        assert_eq!(vec![WinLine::Column(1)], log.wins[0].lines);
+        assert_eq!(5 * (1 + 3 + 4 + 6), log.wins[0].score);
+
+        // No diagonals on a non-square board
+        let log = play(
+            std::slice::from_ref(&b),
+            &[1, 5, 3, 6],
+            &[Pattern::Diagonals],
+        );
+        assert!(log.wins.is_empty());
+        assert_eq!(None, log.last_winner());
+        assert_eq!("1 boards never win\n", log.to_string());
+
+        let log = play(&[b], &[1, 3, 4, 6], &[Pattern::Corners]);
+        assert_eq!(vec![WinLine::Corners], log.wins[0].lines);
+        assert_eq!(6 * (2 + 5), log.wins[0].score);
+    }
+
+    #[test]
+    fn test_new_board() {
+        let b = board(&[&[4, 8], &[15, 16], &[23, 42]]);
+        assert_eq!(Some((0, 1)), b.position(8));
+        assert_eq!(Some((2, 0)), b.position(23));
+        assert_eq!(None, b.position(7));
+
+        assert!(Board::new(0, vec![]).is_err());
+        assert!(Board::new(0, vec![vec![]]).is_err());
+        assert!(Board::new(0, vec![vec![1, 2], vec![3]]).is_err());
+        assert_eq!(
+            Err("3 is on the board more than once".to_string()),
+            Board::new(0, vec![vec![1, 3], vec![3, 4]])
+        );
+    }
+
+    #[test]
+    fn test_patterns() {
+        let b = board(&[&[1, 2, 3], &[4, 5, 6], &[7, 8, 9]]);
+
+        let log = play(std::slice::from_ref(&b), &[3, 5, 7], &[Pattern::Diagonals]);
+        assert_eq!(vec![WinLine::AntiDiagonal], log.wins[0].lines);
+
+        // The middle cell finishes a row and both diagonals at once
+        let patterns = parse_patterns("rows,diagonals").unwrap();
+        let log = play(std::slice::from_ref(&b), &[1, 9, 3, 7, 4, 6, 5], &patterns);
+        assert_eq!(
+            vec![WinLine::Row(1), WinLine::Diagonal, WinLine::AntiDiagonal],
+            log.wins[0].lines
+        );
+        assert_eq!(6, log.wins[0].draw);
+        assert!(log
+            .to_string()
+            .contains("wins with row 2 and the diagonal and the anti-diagonal, score 50"));
+
+        let log = play(
+            std::slice::from_ref(&b),
+            &(1..=9).rev().collect::<Vec<_>>(),
+            &[Pattern::FullCard],
+        );
+        assert_eq!(
+            (8, vec![WinLine::FullCard], 0),
+            (
+                log.wins[0].draw,
+                log.wins[0].lines.clone(),
+                log.wins[0].score
+            )
+        );
+
+        // A repeated draw doesn't count twice
+        let log = play(&[b], &[1, 1, 1, 2], &DEFAULT_PATTERNS);
+        assert!(log.wins.is_empty());
+
+        assert_eq!(
+            Ok(vec![Pattern::Rows, Pattern::Corners, Pattern::FullCard]),
+            parse_patterns("rows, corners,full,rows")
+        );
+        assert!(parse_patterns("rows,stripes").is_err());
+        assert!(parse_patterns("").is_err());
+    }
+
+    #[test]
+    fn test_single_cell() {
+        let b = board(&[&[7]]);
+        for pattern in [
+            Pattern::Rows,
+            Pattern::Columns,
+            Pattern::Diagonals,
+            Pattern::Corners,
+            Pattern::FullCard,
+        ] {
+            let log = play(std::slice::from_ref(&b), &[3, 7], &[pattern]);
+            assert_eq!(Some(1), log.kth_winner(1).map(|w| w.draw));
+        }
+    }
+}
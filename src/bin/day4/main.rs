@@ -1,99 +1,260 @@
+use bingo::Board;
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 
-#[derive(Clone, Debug, Default)]
-struct Board {
-    col_count: Vec<u32>,
-    row_count: Vec<u32>,
-    coords: HashMap<u32, (usize, usize)>,
-    total_sum: u32,
-}
+mod bingo;
 
 type Drawing = Vec<u32>;
 
-fn parse_input(input: &str) -> Result<(Vec<Board>, Drawing), Box<dyn Error>> {
-    let mut lines = input.lines();
+#[derive(Debug, PartialEq)]
+enum ParseErrorKind {
+    EmptyInput,
+    ExpectedBlankLine,
+    NoBoards,
+    UnexpectedBlankLine,
+    NonNumeric { found: String },
+    RepeatedDraw(u32),
+    RaggedRow { expected: usize, found: usize },
+    DuplicateNumber { number: u32, first_line: usize },
+    InvalidBoard(String),
+}
 
-    let drawing: Drawing = lines
-        .next()
-        .ok_or("Empty input")?
-        .split(',')
-        .map(|i| i.parse::<u32>())
-        .collect::<Result<_, _>>()?;
+// Lines count from 1
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    line: usize,
+    kind: ParseErrorKind,
+}
 
-    if lines.next().ok_or("No boards")?.len() != 0 {
-        return Err("Expecting a blank line before boards".into());
-    }
+impl Error for ParseError {}
 
-    let mut boards: Vec<Board> = Vec::new();
-    let mut cur_board: Option<Board> = None;
-    for line in lines {
-        if line.len() == 0 {
-            boards.push(cur_board.take().ok_or("Invalid input")?);
-            continue;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: ", self.line)?;
+
+        match &self.kind {
+            ParseErrorKind::EmptyInput => write!(f, "The input is empty"),
+            ParseErrorKind::ExpectedBlankLine => {
+                write!(f, "Expected a blank line between the draws and the boards")
+            }
+            ParseErrorKind::NoBoards => write!(f, "There are no boards"),
+            ParseErrorKind::UnexpectedBlankLine => {
+                write!(f, "Blank line with no board before it")
+            }
+            ParseErrorKind::NonNumeric { found } => write!(f, "{:?} isn't a number", found),
+            ParseErrorKind::RepeatedDraw(n) => write!(f, "{} is drawn more than once", n),
+            ParseErrorKind::RaggedRow { expected, found } => write!(
+                f,
+                "This row has {} numbers but the board's first row has {}",
+                found, expected
+            ),
+            ParseErrorKind::DuplicateNumber { number, first_line } => write!(
+                f,
+                "{} is already on this board, on line {}",
+                number, first_line
+            ),
+            ParseErrorKind::InvalidBoard(e) => write!(f, "Invalid board: {}", e),
         }
+    }
+}
 
-        if cur_board.is_none() {
-            cur_board = Some(Board::default());
+// A board still being read from `line` on, along with where each of its
+// numbers was seen
+#[derive(Default)]
+struct Draft {
+    line: usize,
+    rows: Vec<Vec<u32>>,
+    seen: HashMap<u32, usize>,
+    bad: bool,
+}
+
+// Strict parsing fails on the first problem. Lenient parsing turns problems
+// into warnings: a bad or repeated draw is skipped, and so is a board with a
+// bad cell, a ragged row or a duplicate number. Problems with the layout of
+// the input are errors either way.
+fn parse(input: &str, lenient: bool) -> Result<(Vec<Board>, Drawing, Vec<ParseError>), ParseError> {
+    let mut warnings = Vec::new();
+    let mut problem = |line: usize, kind: ParseErrorKind| {
+        let err = ParseError { line, kind };
+        if lenient {
+            warnings.push(err);
+            Ok(())
+        } else {
+            Err(err)
         }
+    };
 
-        let cur_board: &mut Board = cur_board.as_mut().unwrap();
-        let r = cur_board.row_count.len();
-        cur_board.row_count.resize(r + 1, 0);
-        for (c, v) in line
-            .split_whitespace()
-            .filter_map(|i| i.parse::<u32>().ok())
-            .enumerate()
-        {
-            if r == 0 {
-                cur_board.col_count.resize(c + 1, 0);
-            }
-            cur_board.col_count[c] += 1;
-            cur_board.row_count[r] += 1;
-            cur_board.coords.insert(v, (r, c));
-            cur_board.total_sum += v;
+    let mut lines = input.lines().enumerate().map(|(i, l)| (i + 1, l));
+
+    let (_, first) = lines.next().ok_or(ParseError {
+        line: 1,
+        kind: ParseErrorKind::EmptyInput,
+    })?;
+    let mut drawing = Drawing::new();
+    for token in first.split(',') {
+        match token.trim().parse::<u32>() {
+            Ok(n) if drawing.contains(&n) => problem(1, ParseErrorKind::RepeatedDraw(n))?,
+            Ok(n) => drawing.push(n),
+            Err(_) => problem(
+                1,
+                ParseErrorKind::NonNumeric {
+                    found: token.to_string(),
+                },
+            )?,
         }
     }
-    if let Some(last_board) = cur_board {
-        boards.push(last_board)
+
+    match lines.next() {
+        None => {
+            return Err(ParseError {
+                line: 2,
+                kind: ParseErrorKind::NoBoards,
+            })
+        }
+        Some((_, l)) if l.trim().is_empty() => (),
+        Some((n, _)) => {
+            return Err(ParseError {
+                line: n,
+                kind: ParseErrorKind::ExpectedBlankLine,
+            })
+        }
     }
 
-    Ok((boards, drawing))
-}
+    let mut drafts: Vec<Draft> = Vec::new();
+    let mut cur: Option<Draft> = None;
+    for (n, line) in lines {
+        if line.trim().is_empty() {
+            match cur.take() {
+                Some(draft) => drafts.push(draft),
+                None => problem(n, ParseErrorKind::UnexpectedBlankLine)?,
+            }
+            continue;
+        }
 
-fn run_games(mut boards: Vec<Board>, drawing: Drawing) -> Option<(u32, u32)> {
-    let mut board_has_won = vec![false; boards.len()];
-    let mut winner_score: Option<u32> = None;
-
-    for n in drawing {
-        for (i, board) in boards.iter_mut().enumerate() {
-            if let Some((r, c)) = board.coords.get(&n) {
-                board.total_sum -= n;
-                board.col_count[*c] -= 1;
-                board.row_count[*r] -= 1;
-                if board.row_count[*r] == 0 || board.col_count[*c] == 0 {
-                    board_has_won[i] = true;
-                    let board_score = n * board.total_sum;
-                    if winner_score.is_none() {
-                        winner_score = Some(board_score);
-                    } else if board_has_won.iter().all(|b| *b) {
-                        return Some((winner_score.unwrap(), board_score));
-                    }
+        let draft = cur.get_or_insert_with(|| Draft {
+            line: n,
+            ..Draft::default()
+        });
+        if draft.bad {
+            continue;
+        }
+
+        let mut row = Vec::new();
+        for token in line.split_whitespace() {
+            match token.parse::<u32>() {
+                Ok(v) => row.push(v),
+                Err(_) => {
+                    draft.bad = true;
+                    problem(
+                        n,
+                        ParseErrorKind::NonNumeric {
+                            found: token.to_string(),
+                        },
+                    )?;
+                    break;
                 }
             }
         }
+        if draft.bad {
+            continue;
+        }
+
+        if let Some(expected) = draft.rows.first().map(|r| r.len()) {
+            if row.len() != expected {
+                draft.bad = true;
+                problem(
+                    n,
+                    ParseErrorKind::RaggedRow {
+                        expected,
+                        found: row.len(),
+                    },
+                )?;
+                continue;
+            }
+        }
+
+        for v in row.iter() {
+            if let Some(first_line) = draft.seen.insert(*v, n) {
+                draft.bad = true;
+                problem(
+                    n,
+                    ParseErrorKind::DuplicateNumber {
+                        number: *v,
+                        first_line,
+                    },
+                )?;
+                break;
+            }
+        }
+        draft.rows.push(row);
+    }
+    drafts.extend(cur);
+
+    if drafts.is_empty() {
+        return Err(ParseError {
+            line: input.lines().count() + 1,
+            kind: ParseErrorKind::NoBoards,
+        });
     }
 
-    None
+    let boards = drafts
+        .into_iter()
+        .enumerate()
+        .filter(|(_, d)| !d.bad)
+        .map(|(i, d)| {
+            Board::new(i, d.rows).map_err(|e| ParseError {
+                line: d.line,
+                kind: ParseErrorKind::InvalidBoard(e),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok((boards, drawing, warnings))
+}
+
+fn parse_input(input: &str) -> Result<(Vec<Board>, Drawing), ParseError> {
+    parse(input, false).map(|(boards, drawing, _)| (boards, drawing))
+}
+
+fn parse_input_lenient(input: &str) -> Result<(Vec<Board>, Drawing, Vec<ParseError>), ParseError> {
+    parse(input, true)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let input = aoc2021::get_input_string()?;
 
-    let (boards, drawing) = parse_input(&input)?;
-    let (part1, part2) = run_games(boards, drawing).unwrap();
-    println!("Part 1: {:?}", part1);
-    println!("Part 2: {:?}", part2);
+    let (boards, drawing) = if aoc2021::has_flag("lenient") {
+        let (boards, drawing, warnings) = parse_input_lenient(&input).map_err(|e| e.to_string())?;
+        for w in warnings {
+            eprintln!("Warning: {}", w);
+        }
+        (boards, drawing)
+    } else {
+        parse_input(&input).map_err(|e| e.to_string())?
+    };
+    let patterns = match aoc2021::get_flag_value("patterns") {
+        Some(p) => bingo::parse_patterns(&p)?,
+        None => bingo::DEFAULT_PATTERNS.to_vec(),
+    };
+    let log = bingo::play(&boards, &drawing, &patterns);
+
+    if aoc2021::has_flag("log") {
+        print!("{}", log);
+        return Ok(());
+    }
+    if let Some(k) = aoc2021::parse_flag_value::<usize>("winner")? {
+        match log.kth_winner(k) {
+            Some(win) => println!("{}", win),
+            None => println!("Only {} boards win", log.wins.len()),
+        }
+        return Ok(());
+    }
+
+    let part1 = log.kth_winner(1).ok_or("No board wins")?;
+    let part2 = log.last_winner().ok_or("Not every board wins")?;
+    println!("Part 1: {}", part1.score);
+    println!("Part 2: {}", part2.score);
 
     Ok(())
 }
@@ -108,10 +269,9 @@ mod tests {
         let (boards, nums) = parse_input(SAMPLE_INPUT).unwrap();
 
         assert_eq!(boards.len(), 3);
-        assert_eq!(boards[0].row_count[0], 5);
-        assert_eq!(boards[1].col_count[4], 5);
-        assert_eq!(boards[0].coords[&14], (2, 2));
-        assert_eq!(boards[2].coords[&14], (0, 0));
+        assert_eq!(boards[0].position(14), Some((2, 2)));
+        assert_eq!(boards[2].position(14), Some((0, 0)));
+        assert_eq!(boards[1].position(1), None);
 
         assert_eq!(nums[3], 5);
     }
@@ -120,8 +280,114 @@ mod tests {
     fn test_game() {
         let (boards, nums) = parse_input(SAMPLE_INPUT).unwrap();
 
-        let (part1, part2) = run_games(boards, nums).unwrap();
-        assert_eq!(part1, 4512);
-        assert_eq!(part2, 1924);
+        let log = bingo::play(&boards, &nums, &bingo::DEFAULT_PATTERNS);
+        assert_eq!(log.kth_winner(1).unwrap().score, 4512);
+        assert_eq!(log.last_winner().unwrap().score, 1924);
+    }
+
+    #[test]
+    fn test_patterns() {
+        let (boards, nums) = parse_input(SAMPLE_INPUT).unwrap();
+
+        // Rows and columns are the only patterns the puzzle counts
+        let log = bingo::play(&boards, &nums, &[bingo::Pattern::FullCard]);
+        assert!(log.kth_winner(1).unwrap().draw > 20);
+    }
+
+    fn kind(input: &str) -> (usize, ParseErrorKind) {
+        let err = parse_input(input).unwrap_err();
+        (err.line, err.kind)
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!((1, ParseErrorKind::EmptyInput), kind(""));
+        assert_eq!((2, ParseErrorKind::NoBoards), kind("1,2"));
+        assert_eq!((3, ParseErrorKind::NoBoards), kind("1,2\n\n"));
+        assert_eq!((2, ParseErrorKind::ExpectedBlankLine), kind("1,2\n1 2"));
+        assert_eq!(
+            (5, ParseErrorKind::UnexpectedBlankLine),
+            kind("1,2\n\n1 2\n\n\n3 4")
+        );
+        assert_eq!(
+            (
+                1,
+                ParseErrorKind::NonNumeric {
+                    found: "x".to_string()
+                }
+            ),
+            kind("1,x,2\n\n1 2")
+        );
+        assert_eq!((1, ParseErrorKind::RepeatedDraw(1)), kind("1,2,1\n\n1 2"));
+        assert_eq!(
+            (
+                4,
+                ParseErrorKind::NonNumeric {
+                    found: "-3".to_string()
+                }
+            ),
+            kind("1,2\n\n1 2\n-3 4")
+        );
+        assert_eq!(
+            (
+                4,
+                ParseErrorKind::RaggedRow {
+                    expected: 2,
+                    found: 3
+                }
+            ),
+            kind("1,2\n\n1 2\n3 4 5")
+        );
+        assert_eq!(
+            (
+                5,
+                ParseErrorKind::DuplicateNumber {
+                    number: 2,
+                    first_line: 3
+                }
+            ),
+            kind("1,2\n\n1 2\n3 4\n5 2")
+        );
+
+        // The same number on different boards is fine
+        assert!(parse_input("1,2\n\n1 2\n\n2 1").is_ok());
+
+        assert_eq!(
+            "Line 5: 2 is already on this board, on line 3",
+            parse_input("1,2\n\n1 2\n3 4\n5 2").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_lenient() {
+        let (boards, drawing, warnings) = parse_input_lenient(SAMPLE_INPUT).unwrap();
+        assert_eq!((3, 27), (boards.len(), drawing.len()));
+        assert!(warnings.is_empty());
+
+        // Board 1 has a bad cell, board 2 a ragged row and board 4 a
+        // duplicate, so only board 3 is left
+        let input = "1,x,2,1,3\n\n1 ?\n2 3\n\n1 2\n3\n\n1 2\n3 4\n\n\n1 1\n2 3";
+        let (boards, drawing, warnings) = parse_input_lenient(input).unwrap();
+        assert_eq!(vec![1, 2, 3], drawing);
+        assert_eq!(1, boards.len());
+        assert_eq!(Some((1, 1)), boards[0].position(4));
+        assert_eq!(
+            vec![1, 1, 3, 7, 12, 13],
+            warnings.iter().map(|w| w.line).collect::<Vec<_>>()
+        );
+        assert_eq!(ParseErrorKind::RepeatedDraw(1), warnings[1].kind);
+        assert_eq!(ParseErrorKind::UnexpectedBlankLine, warnings[4].kind);
+
+        // The board keeps its number from the input
+        let log = bingo::play(&boards, &drawing, &bingo::DEFAULT_PATTERNS);
+        assert_eq!(2, log.wins[0].board);
+        assert_eq!(
+            "Draw 2 (2): board 3 wins with row 1, score 14\n",
+            log.to_string()
+        );
+
+        // Layout problems are still errors
+        assert!(parse_input_lenient("1,2\n1 2").is_err());
+        assert!(parse_input_lenient("").is_err());
     }
 }
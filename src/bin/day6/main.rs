@@ -2,13 +2,13 @@ use std::error::Error;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let input = aoc2021::get_input_string()?;
-    let initial_counts = parse_counts(&input.trim())?;
+    let initial_counts = parse_counts(input.trim())?;
 
-    let population_after_80_days = advance_n_days(initial_counts.clone(), 80usize);
+    let population_after_80_days = advance_n_days(initial_counts, 80usize);
     let total_pop: usize = population_after_80_days.iter().sum();
     println!("Part 1: {}", total_pop);
 
-    let population_after_256_days = advance_n_days(initial_counts.clone(), 256usize);
+    let population_after_256_days = advance_n_days(initial_counts, 256usize);
     let total_pop: usize = population_after_256_days.iter().sum();
     println!("Part 2: {}", total_pop);
 
@@ -38,11 +38,11 @@ fn advance_n_days(mut population: [usize; 9], days: usize) -> [usize; 9] {
 }
 
 fn advance_1_day(population: [usize; 9]) -> [usize; 9] {
-    let mut new_pop = population.clone();
+    let mut new_pop = population;
     new_pop[7] = 0;
     new_pop[8] = 0;
 
-    (&mut new_pop[..7]).rotate_left(1);
+    new_pop[..7].rotate_left(1);
     new_pop[8] += population[0];
     new_pop[7] += population[8];
     new_pop[6] += population[7];
@@ -51,7 +51,7 @@ fn advance_1_day(population: [usize; 9]) -> [usize; 9] {
 }
 
 fn advance_7_days(population: [usize; 9]) -> [usize; 9] {
-    let mut new_pop = population.clone();
+    let mut new_pop = population;
     new_pop[7] = 0;
     new_pop[8] = 0;
 
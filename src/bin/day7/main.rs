@@ -58,10 +58,8 @@ fn part2(mut positions: Vec<i32>) -> usize {
     // to travel x units
     let mut fuel_to: Vec<usize> = Vec::with_capacity(max_pos + 1);
     fuel_to.push(0);
-    let mut delta = 1;
     for i in 1..(max_pos + 1) {
-        fuel_to.push(fuel_to[i - 1] + delta);
-        delta += 1;
+        fuel_to.push(fuel_to[i - 1] + i);
     }
 
     let mut counter: HashMap<i32, usize> = HashMap::new();
@@ -73,7 +71,7 @@ fn part2(mut positions: Vec<i32>) -> usize {
     let mut targets: Vec<usize> = vec![0; max_pos + 1];
     for i in 0i32..=(max_pos as i32) {
         for (pos, count) in counter.iter() {
-            targets[i as usize] += *count * fuel_to[(*pos - i).abs() as usize];
+            targets[i as usize] += *count * fuel_to[(*pos - i).unsigned_abs() as usize];
         }
     }
 
@@ -115,7 +113,7 @@ mod tests {
 
     #[test]
     fn test_part1() {
-        let mut positions = parse_input(SAMPLE_INPUT).unwrap();
+        let positions = parse_input(SAMPLE_INPUT).unwrap();
         let result = part1(positions);
 
         assert_eq!(37, result);
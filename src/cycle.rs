@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+// Finding where a deterministic simulation settles down or starts repeating
+// itself, for the simulators of any day.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    // The step numbered `step` (counting from 1) left the state unchanged
+    FixedPoint { step: usize },
+    // The state after step `start + period` repeats the one after `start`
+    Cycle { start: usize, period: usize },
+    StepLimit,
+}
+
+impl Outcome {
+    // How many steps it takes to see the outcome, None for a step limit
+    pub fn steps(&self) -> Option<usize> {
+        match *self {
+            Outcome::FixedPoint { step } => Some(step),
+            Outcome::Cycle { start, period } => Some(start + period),
+            Outcome::StepLimit => None,
+        }
+    }
+}
+
+// A multiply-rotate hash in the style of FxHash. The state is hashed after
+// every step, and SipHash's resistance to crafted keys isn't needed for that.
+#[derive(Default)]
+struct FastHasher(u64);
+
+impl Hasher for FastHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.write_u64(*b as u64);
+        }
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = (self.0.rotate_left(5) ^ i).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn hash_of<S: Hash>(state: &S) -> u64 {
+    let mut hasher = FastHasher::default();
+    state.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Calls `step` (which makes one full step) on `state` until the state
+// repeats or `max_steps` steps have been made. Only the hash of each state is
+// kept; when a hash turns up again, the earlier state is rebuilt by replaying
+// `step` on a copy of the initial state and compared with the current one.
+//
+// That means `step` gets called more often than the outcome's `steps()`.
+// Anything it records past those steps belongs to the replays.
+pub fn run<S, F>(state: &mut S, max_steps: Option<usize>, mut step: F) -> Outcome
+where
+    S: Clone + PartialEq + Hash,
+    F: FnMut(&mut S),
+{
+    let initial = state.clone();
+    let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+    seen.entry(hash_of(state)).or_default().push(0);
+    let mut prev = state.clone();
+    // The state after `replay.1` steps, moved forward as far as the earlier
+    // states being checked need
+    let mut replay = (initial.clone(), 0);
+
+    for i in 1.. {
+        if max_steps.is_some_and(|max| i > max) {
+            return Outcome::StepLimit;
+        }
+
+        step(state);
+        if *state == prev {
+            return Outcome::FixedPoint { step: i };
+        }
+
+        let earlier = seen.entry(hash_of(state)).or_default();
+        for &start in earlier.iter() {
+            if replay.1 > start {
+                replay = (initial.clone(), 0);
+            }
+            while replay.1 < start {
+                step(&mut replay.0);
+                replay.1 += 1;
+            }
+            if replay.0 == *state {
+                return Outcome::Cycle {
+                    start,
+                    period: i - start,
+                };
+            }
+        }
+        earlier.push(i);
+        prev.clone_from(state);
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts up to `tail`, then goes round the `period` numbers after it
+    fn rho(tail: u64, period: u64) -> impl FnMut(&mut u64) {
+        move |x| {
+            *x = if *x < tail {
+                *x + 1
+            } else {
+                tail + (*x - tail + 1) % period
+            }
+        }
+    }
+
+    #[test]
+    fn test_outcomes() {
+        for (tail, period) in [(0, 2), (1, 3), (5, 7), (100, 19043), (1000, 2)] {
+            let outcome = run(&mut 0u64, None, rho(tail, period));
+            assert_eq!(
+                Outcome::Cycle {
+                    start: tail as usize,
+                    period: period as usize
+                },
+                outcome
+            );
+            assert_eq!(Some((tail + period) as usize), outcome.steps());
+        }
+
+        assert_eq!(
+            Outcome::FixedPoint { step: 13 },
+            run(&mut 0u64, None, rho(12, 1))
+        );
+        assert_eq!(Outcome::StepLimit, run(&mut 0u64, Some(50), rho(40, 11)));
+        assert_eq!(None, Outcome::StepLimit.steps());
+    }
+
+    // Every value hashes the same, so only comparing them tells them apart
+    #[derive(Clone, PartialEq)]
+    struct Collides(u64);
+
+    impl Hash for Collides {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            0.hash(state);
+        }
+    }
+
+    #[test]
+    fn test_hash_collisions() {
+        let mut state = Collides(0);
+        let outcome = run(&mut state, None, |s| s.0 = (s.0 + 1).min(20));
+        assert_eq!(Outcome::FixedPoint { step: 21 }, outcome);
+
+        let mut state = Collides(0);
+        let outcome = run(&mut state, None, |s| s.0 = (s.0 + 1) % 6);
+        assert_eq!(
+            Outcome::Cycle {
+                start: 0,
+                period: 6
+            },
+            outcome
+        );
+    }
+}
@@ -1,15 +1,77 @@
 use std::env;
 use std::error::Error;
 use std::fs;
+use std::io::{self, BufRead, BufReader};
+
+pub mod automaton;
+pub mod cycle;
+pub mod packed;
+pub mod testing;
+pub mod visualize;
+
+// Flags are given as `--name` or `--name=value`; the first argument that
+// isn't a flag is the input file.
+fn positional_args() -> Vec<String> {
+    env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with("--"))
+        .collect()
+}
 
 pub fn get_input_string() -> Result<String, Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
+    let args = positional_args();
+    if args.is_empty() {
         Err(format!(
-            "Usage: {} <input file>",
-            args.get(0).unwrap_or(&"prog".into())
+            "Usage: {} [--flags] <input file>",
+            env::args().next().unwrap_or_else(|| "prog".into())
         ))?;
     }
 
-    fs::read_to_string(&args[1]).map_err(|e| e.into())
+    fs::read_to_string(&args[0]).map_err(|e| e.into())
+}
+
+// The input file opened for reading line by line, with `-` meaning stdin,
+// for inputs that shouldn't be read into memory all at once
+pub fn get_input_reader() -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    let args = positional_args();
+    match args.first().map(String::as_str) {
+        None => Err(format!(
+            "Usage: {} [--flags] <input file or ->",
+            env::args().next().unwrap_or_else(|| "prog".into())
+        ))?,
+        Some("-") => Ok(Box::new(io::stdin().lock())),
+        Some(path) => Ok(Box::new(BufReader::new(
+            fs::File::open(path).map_err(|e| format!("{}: {}", path, e))?,
+        ))),
+    }
+}
+
+pub fn has_flag(name: &str) -> bool {
+    env::args()
+        .skip(1)
+        .any(|a| a.strip_prefix("--") == Some(name))
+}
+
+pub fn get_flag_value(name: &str) -> Option<String> {
+    env::args().skip(1).find_map(|a| {
+        a.strip_prefix("--")?
+            .strip_prefix(name)?
+            .strip_prefix('=')
+            .map(String::from)
+    })
+}
+
+pub fn parse_flag_value<T>(name: &str) -> Result<Option<T>, Box<dyn Error>>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match get_flag_value(name) {
+        Some(v) => {
+            Ok(Some(v.parse::<T>().map_err(|e| {
+                format!("Invalid value for --{}: {}", name, e)
+            })?))
+        }
+        None => Ok(None),
+    }
 }
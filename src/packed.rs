@@ -0,0 +1,39 @@
+// Rows of bits packed 64 to a word, column c of a row being bit c % 64 of
+// word c / 64, as the bit-parallel simulators of several days keep them
+
+pub fn words_for(width: usize) -> usize {
+    width.div_ceil(64)
+}
+
+// Clears the bits past `width` in the last word
+pub fn mask_tail(row: &mut [u64], width: usize) {
+    let tail = width & 63;
+    if tail != 0 {
+        if let Some(last) = row.last_mut() {
+            *last &= (1 << tail) - 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words_for() {
+        assert_eq!(0, words_for(0));
+        assert_eq!(1, words_for(1));
+        assert_eq!(1, words_for(64));
+        assert_eq!(2, words_for(65));
+    }
+
+    #[test]
+    fn test_mask_tail() {
+        let mut row = vec![!0u64; 2];
+        mask_tail(&mut row, 128);
+        assert_eq!(vec![!0, !0], row);
+        mask_tail(&mut row, 70);
+        assert_eq!(vec![!0, 0b11_1111], row);
+        mask_tail(&mut [], 5);
+    }
+}
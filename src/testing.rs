@@ -0,0 +1,15 @@
+// Helpers shared by the tests and benchmarks of the days. Benchmarks are
+// ignored tests: cargo test --release --bin dayN -- --ignored --nocapture
+
+// xorshift64, so that randomised tests are reproducible without pulling in a
+// dependency. The seed must not be 0.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
@@ -0,0 +1,276 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+// Terminal animation for the grid puzzles. Each frame is redrawn in place with
+// ANSI escape codes. Since stdin stays line-buffered, controls are typed as a
+// line each: Enter pauses or resumes, `s` + Enter steps one frame while
+// paused, and `q` + Enter stops the animation.
+//
+// Frames can also be recorded to a file, separated by form feed lines, and
+// played back later with `replay`.
+
+const HELP: &str = "[Enter] pause/resume  [s Enter] step  [q Enter] quit";
+const FRAME_SEPARATOR: &str = "\x0c\n";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Control {
+    Continue,
+    Quit,
+}
+
+pub struct Visualizer {
+    delay: Duration,
+    paused: bool,
+    // Set when frames are drawn to the terminal
+    terminal: Option<io::Stdout>,
+    commands: Option<Receiver<String>>,
+    recording: Option<BufWriter<File>>,
+}
+
+// Lines typed on stdin, read on a thread of their own so that the animation
+// doesn't have to wait for them
+fn spawn_stdin_reader() -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn delay_from_flags() -> Result<Duration, Box<dyn Error>> {
+    let fps = crate::parse_flag_value::<f64>("fps")?.unwrap_or(10.0);
+    if !(fps > 0.0 && fps.is_finite()) {
+        return Err(format!("Invalid value for --fps: {}", fps).into());
+    }
+    Ok(Duration::from_secs_f64(1.0 / fps))
+}
+
+// Clears the screen and hides the cursor (until the visualizer is dropped)
+fn open_terminal() -> io::Result<io::Stdout> {
+    let mut out = io::stdout();
+    write!(out, "\x1b[2J\x1b[?25l")?;
+    Ok(out)
+}
+
+fn split_frames(recording: &str) -> Vec<&str> {
+    recording
+        .split(FRAME_SEPARATOR)
+        .filter(|frame| !frame.is_empty())
+        .collect()
+}
+
+impl Visualizer {
+    // `--visualize` draws to the terminal at `--fps=N` frames per second
+    // (default 10), and `--record=FILE` saves the frames. None if neither is
+    // asked for.
+    pub fn from_flags() -> Result<Option<Visualizer>, Box<dyn Error>> {
+        let display = crate::has_flag("visualize");
+        let record = crate::get_flag_value("record");
+        if !display && record.is_none() {
+            return Ok(None);
+        }
+
+        let delay = delay_from_flags()?;
+        let recording = match record {
+            Some(path) => Some(BufWriter::new(
+                File::create(&path).map_err(|e| format!("{}: {}", path, e))?,
+            )),
+            None => None,
+        };
+
+        let (terminal, commands) = if display {
+            (Some(open_terminal()?), Some(spawn_stdin_reader()))
+        } else {
+            (None, None)
+        };
+
+        Ok(Some(Visualizer {
+            delay,
+            paused: false,
+            terminal,
+            commands,
+            recording,
+        }))
+    }
+
+    // Draws and/or records one frame, then waits for the next one to be due
+    pub fn show(&mut self, title: &str, grid: &str) -> Result<Control, Box<dyn Error>> {
+        let frame = format!("{}\n{}\n", title, grid.trim_end_matches('\n'));
+        if let Some(recording) = self.recording.as_mut() {
+            recording.write_all(frame.as_bytes())?;
+            recording.write_all(FRAME_SEPARATOR.as_bytes())?;
+        }
+        self.draw(&frame)
+    }
+
+    fn draw(&mut self, frame: &str) -> Result<Control, Box<dyn Error>> {
+        let out = match self.terminal.as_mut() {
+            Some(out) => out,
+            None => return Ok(Control::Continue),
+        };
+
+        // Home the cursor, draw over the last frame and clear whatever of it
+        // is left below
+        write!(out, "\x1b[H")?;
+        for line in frame.lines() {
+            writeln!(out, "{}\x1b[K", line)?;
+        }
+        let status = if self.paused { "paused" } else { "playing" };
+        write!(out, "\n{}  ({})\x1b[K\n\x1b[J", HELP, status)?;
+        out.flush()?;
+
+        if !self.paused {
+            thread::sleep(self.delay);
+        }
+        Ok(self.handle_commands())
+    }
+
+    // Acts on the lines typed since the last frame. While paused this blocks
+    // until the user steps, resumes or quits.
+    fn handle_commands(&mut self) -> Control {
+        let commands = match self.commands.as_ref() {
+            Some(commands) => commands,
+            None => return Control::Continue,
+        };
+
+        loop {
+            let line = if self.paused {
+                match commands.recv() {
+                    Ok(line) => line,
+                    // Nobody is left to unpause us
+                    Err(_) => {
+                        self.paused = false;
+                        return Control::Continue;
+                    }
+                }
+            } else {
+                match commands.try_recv() {
+                    Ok(line) => line,
+                    Err(TryRecvError::Empty | TryRecvError::Disconnected) => {
+                        return Control::Continue
+                    }
+                }
+            };
+
+            match line.trim() {
+                "q" => return Control::Quit,
+                "s" if self.paused => return Control::Continue,
+                "" | "p" => self.paused = !self.paused,
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drop for Visualizer {
+    fn drop(&mut self) {
+        if let Some(recording) = self.recording.as_mut() {
+            let _ = recording.flush();
+        }
+        if let Some(out) = self.terminal.as_mut() {
+            // Show the cursor again
+            let _ = write!(out, "\x1b[?25h");
+            let _ = out.flush();
+        }
+    }
+}
+
+// Plays back a file written with `--record`, using the same controls and
+// `--fps` as a live animation
+pub fn replay(path: &str) -> Result<(), Box<dyn Error>> {
+    let recording = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut visualizer = Visualizer {
+        delay: delay_from_flags()?,
+        paused: false,
+        terminal: Some(open_terminal()?),
+        commands: Some(spawn_stdin_reader()),
+        recording: None,
+    };
+
+    for frame in split_frames(&recording) {
+        if visualizer.draw(frame)? == Control::Quit {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visualizer(commands: Receiver<String>) -> Visualizer {
+        Visualizer {
+            delay: Duration::ZERO,
+            paused: false,
+            terminal: None,
+            commands: Some(commands),
+            recording: None,
+        }
+    }
+
+    #[test]
+    fn test_controls() {
+        let (tx, rx) = mpsc::channel();
+        let mut v = visualizer(rx);
+
+        assert_eq!(Control::Continue, v.handle_commands());
+
+        // Pause, step twice, then resume
+        for line in ["", "s", "s", "p"] {
+            tx.send(line.to_string()).unwrap();
+        }
+        assert_eq!(Control::Continue, v.handle_commands());
+        assert!(v.paused);
+        assert_eq!(Control::Continue, v.handle_commands());
+        assert!(v.paused);
+        assert_eq!(Control::Continue, v.handle_commands());
+        assert!(!v.paused);
+
+        // Stepping while playing does nothing
+        tx.send("s".to_string()).unwrap();
+        assert_eq!(Control::Continue, v.handle_commands());
+        assert!(!v.paused);
+
+        tx.send(" q ".to_string()).unwrap();
+        assert_eq!(Control::Quit, v.handle_commands());
+
+        // With stdin closed a paused animation carries on
+        tx.send(String::new()).unwrap();
+        drop(tx);
+        assert_eq!(Control::Continue, v.handle_commands());
+        assert!(!v.paused);
+    }
+
+    #[test]
+    fn test_record_and_split() {
+        let path = std::env::temp_dir().join(format!("aoc2021-record-{}.txt", std::process::id()));
+        let mut v = Visualizer {
+            delay: Duration::ZERO,
+            paused: false,
+            terminal: None,
+            commands: None,
+            recording: Some(BufWriter::new(File::create(&path).unwrap())),
+        };
+
+        assert_eq!(Control::Continue, v.show("Step 0", "#.\n.#\n").unwrap());
+        assert_eq!(Control::Continue, v.show("Step 1", ".#\n#.").unwrap());
+        drop(v);
+
+        let recording = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            vec!["Step 0\n#.\n.#\n", "Step 1\n.#\n#.\n"],
+            split_frames(&recording)
+        );
+    }
+}